@@ -49,6 +49,23 @@ impl DynumType {
         1 << Self::max_value_info().bitrange - 1
     }
 
+    /// Smallest type that can represent `num`
+    fn for_value(num: u64) -> Result<DynumType,DynumError> {
+        Self::as_list()
+            .into_iter()
+            .find(|d| num >> d.get_typeinfo().bitrange == 0)
+            .ok_or(DynumError::NumberOutsideOfRange(num))
+    }
+
+    /// Type of an encoded number, given its first byte
+    fn for_tag(first: u8) -> Result<DynumType,DynumError> {
+        Self::as_list().into_iter().find(|d| {
+            let info = d.get_typeinfo();
+            let invts = 8 - info.tagsize;
+            (first << invts) >> invts == info.tagcode
+        }).ok_or(DynumError::InvalidTag(first))
+    }
+
     fn max_value_info() -> DynumTypeInfo{
         Self::as_list().last().unwrap().get_typeinfo()
     }
@@ -88,15 +105,7 @@ impl DynumType {
 /// This closure gets called for every generated byte
 /// It returns either the number of generated bytes or an error message if the given value exceeds the supported range.
 pub fn encode_into(mut num: u64, mut target: impl FnMut(u8)) -> Result<u8,DynumError> {
-    let typ = DynumType::as_list()
-        .into_iter()
-        .find(|d| {
-            let info = d.get_typeinfo();
-            num >> info.bitrange == 0
-        })
-        .ok_or(DynumError::NumberOutsideOfRange(num))?;
-
-    let typdata = typ.get_typeinfo();
+    let typdata = DynumType::for_value(num)?.get_typeinfo();
 
     //Insert tagdata
     num <<= typdata.tagsize;
@@ -109,25 +118,30 @@ pub fn encode_into(mut num: u64, mut target: impl FnMut(u8)) -> Result<u8,DynumE
     Ok(typdata.bytecapacity)
 }
 
+/// Number of bytes [encode_into] generates for `num`, without encoding it.
+pub fn encoded_len(num: u64) -> Result<u8,DynumError> {
+    Ok(DynumType::for_value(num)?.get_typeinfo().bytecapacity)
+}
+
+/// Total number of bytes of an encoded number, given its first byte.
+/// Allows to check that a number is complete before decoding it.
+pub fn encoded_len_from_tag(first: u8) -> Result<u8,DynumError> {
+    Ok(DynumType::for_tag(first)?.get_typeinfo().bytecapacity)
+}
 
 /// Decode a binary stream into a u64 value using the appropriate dynamic number type.
 /// Returns none if the first read byte doesn't contains a valid tag
 pub fn decode_binary_stream<T: Borrow<u8>>(input: &mut impl Iterator<Item = T>) -> Result<u64,DynumError> {
     let first: u8 = *input.next().ok_or(DynumError::NoByteProvided)?.borrow();
 
-    let typ = DynumType::as_list().into_iter().find(|d| {
-        let info = d.get_typeinfo();
-        let invts = 8 - info.tagsize;
-        (first << invts) >> invts == info.tagcode
-    }).ok_or(DynumError::InvalidTag(first))?;
-    let typdata = typ.get_typeinfo();
+    let typdata = DynumType::for_tag(first)?.get_typeinfo();
 
     let mut buff = [0u8; std::mem::size_of::<u64>()];
     buff[0] = first;
     for i in 0..typdata.bytecapacity - 1 {
         buff[(i + 1) as usize] = *input.next()
         .ok_or(DynumError::UnexpectedEOI{
-            expected: typdata.tagsize+typdata.bitrange,
+            expected: typdata.bytecapacity,
             got: i+1
         })?.borrow();
     }
@@ -136,6 +150,32 @@ pub fn decode_binary_stream<T: Borrow<u8>>(input: &mut impl Iterator<Item = T>)
     Ok(u64::from_le_bytes(buff) >> typdata.tagsize)
 }
 
+/// Maps a signed number onto an unsigned one using zigzag encoding
+/// (0, -1, 1, -2, 2, ... => 0, 1, 2, 3, 4, ...), so values with a small magnitude stay small.
+pub const fn zigzag_encode(num: i64) -> u64 {
+    ((num << 1) ^ (num >> 63)) as u64
+}
+
+/// Reverses [zigzag_encode]
+pub const fn zigzag_decode(num: u64) -> i64 {
+    ((num >> 1) as i64) ^ -((num & 1) as i64)
+}
+
+/// Signed counterpart of [encode_into] - the number gets zigzag encoded before it is written.
+pub fn encode_signed_into(num: i64, target: impl FnMut(u8)) -> Result<u8,DynumError> {
+    encode_into(zigzag_encode(num), target)
+}
+
+/// Signed counterpart of [encoded_len]
+pub fn encoded_signed_len(num: i64) -> Result<u8,DynumError> {
+    encoded_len(zigzag_encode(num))
+}
+
+/// Signed counterpart of [decode_binary_stream]
+pub fn decode_signed_binary_stream<T: Borrow<u8>>(input: &mut impl Iterator<Item = T>) -> Result<i64,DynumError> {
+    decode_binary_stream(input).map(zigzag_decode)
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -165,6 +205,38 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_signed_range(){
+        let maxval = (DynumType::max_value() >> 1) as i64;
+        (-(1i64 << 18)..1 << 18).into_par_iter().for_each(|i|{
+            for val in [i, maxval - i.abs(), -maxval + i.abs()]{
+                let mut buff = Vec::with_capacity(8);
+                encode_signed_into(val, |a| buff.push(a)).unwrap();
+                assert_eq!(val, decode_signed_binary_stream(&mut buff.iter()).unwrap());
+            }
+        });
+        let mut buff = Vec::new();
+        encode_signed_into(-1, |a| buff.push(a)).unwrap();
+        assert_eq!(buff.len(), 1);
+    }
+
+    #[test]
+    fn test_encoded_len(){
+        for val in [0, 127, 128, 1 << 14, 1 << 29, DynumType::max_value()] {
+            let mut buff = Vec::new();
+            let written = encode_into(val, |a| buff.push(a)).unwrap();
+            assert_eq!(encoded_len(val).unwrap(), written);
+            assert_eq!(buff.len(), written as usize);
+        }
+        assert_eq!(encoded_signed_len(-64).unwrap(), 1);
+        for val in [0, 1 << 7, 1 << 14, 1 << 29] {
+            let mut buff = Vec::new();
+            encode_into(val, |a| buff.push(a)).unwrap();
+            assert_eq!(encoded_len_from_tag(buff[0]).unwrap() as usize, buff.len());
+        }
+        assert!(encoded_len(u64::MAX).is_err());
+    }
+
     #[test]
     fn test_example(){
         let mut buff = Vec::with_capacity(10);
@@ -0,0 +1,126 @@
+//! Annotated dump of an encoded message, like `protoc --decode`:
+//! `smith-dump -f schema.bdr -t Packet message.bin`
+//! or `echo "[1, 5, 3]" | smith-dump -f schema.bdr -t Packet --bytes`
+use std::io::Read;
+use std::path::PathBuf;
+
+use smith_core::{Endianness, Smith, StringEncoding, WireFormat};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "smith-dump")]
+pub struct Opt {
+    #[structopt(short = "f", long, parse(from_os_str))]
+    pub schema_file: PathBuf,
+
+    /// Type of the message
+    #[structopt(short = "t", long = "type")]
+    pub typ: String,
+
+    /// Message file, stdin if not given
+    #[structopt(parse(from_os_str))]
+    pub input: Option<PathBuf>,
+
+    /// Input is a list of decimal bytes, e.g. `[1, 5, 3]`
+    #[structopt(long, conflicts_with = "hex")]
+    pub bytes: bool,
+
+    /// Input is hex text, whitespace is ignored
+    #[structopt(long)]
+    pub hex: bool,
+
+    #[structopt(long)]
+    pub little_endian: bool,
+
+    #[structopt(long)]
+    pub length_prefixed: bool,
+}
+
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {msg}");
+    std::process::exit(1);
+}
+
+/// Bytes of `text` in the given notation
+fn parse(text: &str, opt: &Opt) -> Result<Vec<u8>, String> {
+    if opt.bytes {
+        return text
+            .split(|c: char| c == ',' || c == '[' || c == ']' || c.is_whitespace())
+            .filter(|b| !b.is_empty())
+            .map(|b| b.parse().map_err(|_| format!("'{b}' is not a byte")))
+            .collect();
+    }
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(c) = digits.iter().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("'{c}' is not a hex digit"));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("'{pair}' is not a hex byte"))
+        })
+        .collect()
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let schema = std::fs::read_to_string(&opt.schema_file)
+        .unwrap_or_else(|err| fail(format!("reading schema file: {err}")));
+
+    let mut input = Vec::new();
+    match &opt.input {
+        Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut input)),
+        None => std::io::stdin().read_to_end(&mut input),
+    }
+    .unwrap_or_else(|err| fail(format!("reading message: {err}")));
+    if opt.bytes || opt.hex {
+        let text = String::from_utf8(input).unwrap_or_else(|err| fail(err));
+        input = parse(&text, &opt).unwrap_or_else(|err| fail(err));
+    }
+
+    let mut format = WireFormat::default();
+    if opt.little_endian {
+        format = format.with_endianness(Endianness::Little);
+    }
+    if opt.length_prefixed {
+        format = format.with_strings(StringEncoding::LengthPrefixed);
+    }
+    let smith = Smith::new(&schema).with_format(format);
+    let typ = smith
+        .get_type(&opt.typ)
+        .unwrap_or_else(|| fail(format!("type '{}' not found in the schema", opt.typ)));
+
+    let dump = smith.dump(&input, &typ);
+    print!("{dump}");
+    if dump.error.is_some() {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn opt(notation: &str) -> Opt {
+        Opt::from_iter(["smith-dump", "-f", "schema.bdr", "-t", "Packet", notation])
+    }
+
+    #[test]
+    fn test_parse() {
+        let hex = opt("--hex");
+        assert_eq!(parse("01 a0\nFF", &hex).unwrap(), [1, 0xa0, 0xff]);
+        assert!(parse("", &hex).unwrap().is_empty());
+        assert_eq!(parse("0g", &hex).unwrap_err(), "'g' is not a hex digit");
+        assert_eq!(parse("+1", &hex).unwrap_err(), "'+' is not a hex digit");
+        assert_eq!(parse("01 2", &hex).unwrap_err(), "odd number of hex digits (3)");
+
+        let bytes = opt("--bytes");
+        assert_eq!(parse("[1, 5,3]", &bytes).unwrap(), [1, 5, 3]);
+        assert_eq!(parse("[1, 256]", &bytes).unwrap_err(), "'256' is not a byte");
+        assert_eq!(parse("[-1]", &bytes).unwrap_err(), "'-1' is not a byte");
+    }
+}
@@ -11,7 +11,7 @@ use quote::{format_ident, quote};
 use quote::__private::TokenStream;
 use rust_format::{Formatter, RustFmt};
 use smith_core::generics_engine::SmithCustomTyp;
-use smith_core::parser::{ASTRootType, ParsedEnum, ParsedStruct, RootDeclaration};
+use smith_core::parser::{ASTRootType, ParsedEnum, ParsedFlags, ParsedStruct, RootDeclaration};
 
 pub fn generate(b: &SmithProgram<usize>) -> Result<String, String> {
     RustFmt::default()
@@ -38,6 +38,7 @@ pub fn generate_tokens(b: &SmithProgram<usize>) -> TokenStream {
             match rootdec.typ(){
                 ASTRootType::Struct(v) => {generate_struct(q_generic,v)}
                 ASTRootType::Enum(v) => {generate_enum(q_generic,v)}
+                ASTRootType::Flags(v) => {generate_flags(v)}
             }
 
         })
@@ -90,6 +91,33 @@ fn generate_enum(q_generic: TokenStream, val: &ParsedEnum) -> TokenStream{
 
 
 
+//Transparent over the bits - the serializer maps integers to the flag bitmask
+fn generate_flags(val: &ParsedFlags) -> TokenStream{
+    let q_name = format_ident!("{}",val.name);
+    let q_bits = format_ident!("{}", match val.flags.len() {
+        0..=8 => "u8",
+        9..=16 => "u16",
+        17..=32 => "u32",
+        _ => "u64",
+    });
+    let q_flags = val.flags.iter().enumerate().map(|(i,f)|{
+        let ident = format_ident!("{}",f);
+        let i: TokenStream = i.to_string().parse().unwrap();
+        quote!(const #ident = 1 << #i;)
+    });
+    quote!(
+        #[derive(Serialize,Deserialize,PartialEq,Eq,Hash,Debug,Clone,Copy)]
+        #[serde(transparent)]
+        pub struct #q_name(#q_bits);
+
+        bitflags::bitflags!{
+            impl #q_name: #q_bits{
+                #(#[allow(non_upper_case_globals)] #q_flags)*
+            }
+        }
+    )
+}
+
 fn as_rust_type2(typ: &SmithType<String>) -> TokenStream {
     if let SmithType::Array(typ) = typ {
         let typ = as_rust_type2(typ);
@@ -98,6 +126,42 @@ fn as_rust_type2(typ: &SmithType<String>) -> TokenStream {
         );
     }
 
+    if let SmithType::FixedArray(typ, len) = typ {
+        let typ = as_rust_type2(typ);
+        let len: TokenStream = len.to_string().parse().unwrap();
+        return quote!(
+            [#typ; #len]
+        );
+    }
+
+    if let SmithType::Option(typ) = typ {
+        let typ = as_rust_type2(typ);
+        return quote!(
+            Option<#typ>
+        );
+    }
+
+    if let SmithType::Map(k, v) = typ {
+        let k = as_rust_type2(k);
+        let v = as_rust_type2(v);
+        return quote!(
+            std::collections::HashMap<#k, #v>
+        );
+    }
+
+    if let SmithType::Bytes = typ {
+        return quote!(Vec<u8>);
+    }
+
+    //Well-known types map to the ecosystem types, serialized as their canonical string
+    match typ {
+        SmithType::Timestamp => return quote!(chrono::DateTime<chrono::Utc>),
+        SmithType::Duration => return quote!(std::time::Duration),
+        SmithType::Uuid => return quote!(uuid::Uuid),
+        SmithType::Decimal => return quote!(String),
+        _ => {}
+    }
+
     if let SmithType::CustomType(name,gen) = typ{
         let name = format_ident!("{name}");
         let gen = if gen.len() == 0{
@@ -122,6 +186,7 @@ fn as_rust_type2(typ: &SmithType<String>) -> TokenStream {
             SmithType::U32 => "u32",
             SmithType::U64 => "u64",
             SmithType::UInt => "u64",
+            SmithType::Int => "i64",
             SmithType::Bool => "bool",
             SmithType::String => "String",
             _=>{panic!()}
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
-use smith_core::{SmithProgram, SmithType, parser::{ASTRootType, ParsedStruct, ParsedEnum}};
+use smith_core::{SmithProgram, SmithType};
+use smith_core::parser::{ASTRootType, ParsedStruct, ParsedEnum, ParsedFlags};
 
 
 pub fn generate(b: &SmithProgram<usize>) -> Result<String, String> {
@@ -16,6 +17,7 @@ pub fn generate(b: &SmithProgram<usize>) -> Result<String, String> {
       match rootdec.typ(){
           ASTRootType::Struct(v) => {generate_struct(q_generic,v)}
           ASTRootType::Enum(v) => {generate_enum(q_generic, v)}
+          ASTRootType::Flags(v) => {generate_flags(v)}
       }
    });
    let s = res.collect::<Vec<String>>().join("\n\n");
@@ -35,6 +37,16 @@ fn generate_struct(q_generic: String, val: &ParsedStruct) -> String{
    format!("export interface {}{q_generic}{{\n{q_field}\n}}",val.name)
 }
 
+//Json represents flags as array of the set flag names
+fn generate_flags(val: &ParsedFlags) -> String{
+   let names = val.flags
+      .iter()
+      .map(|f|format!("'{f}'"))
+      .collect::<Vec<_>>().join(" | ");
+   let names = if names.is_empty() {"never".to_owned()} else {names};
+   format!("export type {} = ({names})[]",val.name)
+}
+
 fn generate_enum(q_generic: String, val: &ParsedEnum) -> String{
    let name = &val.name;
 
@@ -121,8 +133,15 @@ fn as_js_type(typ: &SmithType<String>) -> String {
       SmithType::U32 => "number".to_string(),
       SmithType::U64 => "number".to_string(),
       SmithType::UInt => "number".to_string(),
+      SmithType::Int => "number".to_string(),
       SmithType::Bool => "boolean".to_string(),
       SmithType::String => "string".to_string(),
+      SmithType::Bytes => "Uint8Array".to_string(),
+      //Dates are accepted for encoding, decoding yields the ISO-8601 string
+      SmithType::Timestamp => "Date | string".to_string(),
+      SmithType::Duration => "string".to_string(),
+      SmithType::Uuid => "string".to_string(),
+      SmithType::Decimal => "string".to_string(),
       SmithType::CustomType(name,gen) => {
          let mut n = name.clone();
          if gen.len() > 0{
@@ -133,6 +152,18 @@ fn as_js_type(typ: &SmithType<String>) -> String {
          }
          n
       },
-      SmithType::Array(typ) => format!("{}[]", as_js_type(&typ)),
+      SmithType::Array(typ) => match **typ {
+         SmithType::Option(_) | SmithType::Timestamp => format!("({})[]", as_js_type(typ)),
+         _ => format!("{}[]", as_js_type(typ)),
+      },
+      //Small arrays are typed as sized tuple
+      SmithType::FixedArray(typ, len) if *len <= 16 => {
+         format!("[{}]", vec![as_js_type(typ); *len].join(", "))
+      }
+      SmithType::FixedArray(typ, _) => format!("{}[]", as_js_type(typ)),
+      SmithType::Option(typ) => format!("{} | null", as_js_type(typ)),
+      //Maps are transported as json objects - a `Map` would be dropped by `JSON.stringify`.
+      //Encoding also accepts arrays of [key, value] pairs
+      SmithType::Map(k, v) => format!("Record<{}, {}>", as_js_type(k), as_js_type(v)),
    }
 }
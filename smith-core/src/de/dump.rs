@@ -0,0 +1,379 @@
+//! Annotated dump of an encoded message - every byte range with the path, schema type and value
+//! it holds, see [crate::Smith::dump]
+use std::fmt::{self, Display, Write};
+use std::ops::Range;
+
+use super::Deserializer;
+use crate::generics_engine::SmithCustomTypVariant;
+use crate::resolver::ResolvedSmithProgram;
+use crate::smith_serde::{root_name, Error};
+use crate::value::SmithValue;
+use crate::{par, wellknown, SmithType};
+
+/// Bytes shown per line, longer ranges are cut
+const SHOWN_BYTES: usize = 8;
+
+/// Byte range of the message and what it encodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DumpLine {
+    /// Empty for lines opening a struct
+    pub range: Range<usize>,
+    /// Same paths as [Error::path]
+    pub path: String,
+    /// Schema type, udInt / dInt with the tag bits of their first byte
+    pub typ: String,
+    pub value: String,
+}
+
+/// Where decoding failed, the bytes after `offset` were not decoded
+#[derive(Debug)]
+pub struct DumpError {
+    pub offset: usize,
+    pub path: String,
+    pub error: Error,
+}
+
+/// Result of [crate::Smith::dump], its `Display` renders a table
+#[derive(Debug)]
+pub struct Dump<'a> {
+    pub data: &'a [u8],
+    pub lines: Vec<DumpLine>,
+    pub error: Option<DumpError>,
+}
+
+impl Dump<'_> {
+    /// Writes the dump of the message `de` reads, its header has to be read already
+    pub fn read<'de>(mut de: Deserializer<'de, 'de>, data: &'de [u8]) -> Dump<'de> {
+        let mut dumper = Dumper {
+            lines: Vec::new(),
+            error: None,
+        };
+        //The header is the only byte before the value
+        if de.offset() > 0 {
+            dumper.lines.push(DumpLine {
+                range: 0..de.offset(),
+                path: String::new(),
+                typ: "header".to_owned(),
+                value: format!("{:?}", de.format),
+            });
+        }
+        let typ = de.current_type;
+        let mut path = root_name(typ, de.prog).to_owned();
+        if dumper.value(&mut de, typ, &mut path).is_some() {
+            if let Err(error) = de.end() {
+                dumper.fail(de.offset(), "", error);
+            }
+        }
+        Dump {
+            data,
+            lines: dumper.lines,
+            error: dumper.error,
+        }
+    }
+}
+
+struct Dumper {
+    lines: Vec<DumpLine>,
+    error: Option<DumpError>,
+}
+
+impl Dumper {
+    /// Records the error decoding stopped with, always `None`
+    fn fail(&mut self, offset: usize, path: &str, error: Error) -> Option<()> {
+        self.error = Some(DumpError {
+            offset,
+            path: path.to_owned(),
+            error,
+        });
+        None
+    }
+
+    /// Adds the line of a value `read` reads, `None` if it failed
+    fn leaf<'de>(
+        &mut self,
+        de: &mut Deserializer<'de, 'de>,
+        path: &str,
+        typ: &str,
+        read: impl FnOnce(&mut Deserializer<'de, 'de>) -> Result<String, Error>,
+    ) -> Option<()> {
+        let start = de.offset();
+        //udInt / dInt lengths, variants and numbers show their tag
+        let typ = match (typ, de.remaining().first()) {
+            ("udInt" | "dInt" | "length" | "variant", Some(first)) => match dynum_tag(*first) {
+                Some(tag) => format!("{typ} (tag {tag})"),
+                None => format!("{typ} (invalid tag)"),
+            },
+            _ => typ.to_owned(),
+        };
+        match read(de) {
+            Ok(value) => {
+                self.lines.push(DumpLine {
+                    range: start..de.offset(),
+                    path: path.to_owned(),
+                    typ,
+                    value,
+                });
+                Some(())
+            }
+            //Reads fail before consuming anything
+            Err(error) => self.fail(start, path, error),
+        }
+    }
+
+    fn value<'de>(
+        &mut self,
+        de: &mut Deserializer<'de, 'de>,
+        typ: &'de SmithType<usize>,
+        path: &mut String,
+    ) -> Option<()> {
+        de.current_type = typ;
+        let name = type_name(typ, de.prog);
+        match typ {
+            SmithType::Option(inner) => {
+                let mut present = false;
+                self.leaf(de, path, &name, |de| {
+                    present = de.read_bool()?;
+                    Ok(if present { "Some" } else { "None" }.to_owned())
+                })?;
+                if present {
+                    self.value(de, inner, path)?;
+                }
+            }
+            SmithType::Array(elemtyp) | SmithType::FixedArray(elemtyp, _) => {
+                let mut len = match typ {
+                    SmithType::FixedArray(_, len) => *len,
+                    _ => 0,
+                };
+                if let SmithType::Array(_) = typ {
+                    let first = Dynum::decode_binary_stream(&mut de.remaining().iter());
+                    let chunked = first.ok() == Some(par::CHUNK_TABLE_MARKER);
+                    let typ = if chunked { "chunk table" } else { "length" };
+                    self.leaf(de, path, typ, |de| {
+                        len = de.read_array_len(elemtyp)?;
+                        Ok(format!("len {len}"))
+                    })?;
+                }
+                self.elements(de, path, len, |dumper, de, path| {
+                    dumper.value(de, elemtyp, path)
+                })?;
+            }
+            SmithType::Map(keytyp, valtyp) => {
+                let mut len = 0;
+                self.leaf(de, path, "length", |de| {
+                    let entries = de.read_udint()?;
+                    len = de.check_elements(entries, &[keytyp, valtyp])?;
+                    Ok(format!("len {len}"))
+                })?;
+                self.elements(de, path, len, |dumper, de, path| {
+                    dumper.value(de, keytyp, path)?;
+                    dumper.value(de, valtyp, path)
+                })?;
+            }
+            SmithType::CustomType(id, _) => {
+                let Some(custom) = de.prog.get(*id) else {
+                    return self.fail(de.offset(), path, Error::CustomTypeNotFoundById);
+                };
+                match &custom.variant {
+                    SmithCustomTypVariant::Struct(s) => {
+                        let start = de.offset();
+                        self.lines.push(DumpLine {
+                            range: start..start,
+                            path: path.clone(),
+                            typ: name,
+                            value: String::new(),
+                        });
+                        self.nested(de, path, |dumper, de, path| {
+                            for (field, fieldtyp) in &s.fields {
+                                let len = path.len();
+                                write!(path, ".{field}").ok()?;
+                                dumper.value(de, fieldtyp, path)?;
+                                path.truncate(len);
+                            }
+                            Some(())
+                        })?;
+                    }
+                    SmithCustomTypVariant::Enum(_) => {
+                        let mut variant = None;
+                        self.leaf(de, path, "variant", |de| {
+                            let v = de.current_variant()?;
+                            variant = Some(v);
+                            Ok(v.0.clone())
+                        })?;
+                        if let Some((variant, Some(payload))) = variant {
+                            let len = path.len();
+                            write!(path, "<{variant}>").ok()?;
+                            self.nested(de, path, |dumper, de, path| {
+                                dumper.value(de, payload, path)
+                            })?;
+                            path.truncate(len);
+                        }
+                    }
+                    SmithCustomTypVariant::Flags(f) => {
+                        self.leaf(de, path, &name, |de| {
+                            let mask = de.read_flags(f)?;
+                            Ok(f.names(mask).collect::<Vec<_>>().join(" | "))
+                        })?;
+                    }
+                }
+            }
+            _ => {
+                let prog = de.prog;
+                self.leaf(de, path, &name, |de| scalar(de, typ, prog))?;
+            }
+        }
+        Some(())
+    }
+
+    /// Elements of arrays and entries of maps, `value` reads one
+    fn elements<'de>(
+        &mut self,
+        de: &mut Deserializer<'de, 'de>,
+        path: &mut String,
+        len: usize,
+        mut value: impl FnMut(&mut Self, &mut Deserializer<'de, 'de>, &mut String) -> Option<()>,
+    ) -> Option<()> {
+        self.nested(de, path, |dumper, de, path| {
+            for i in 0..len {
+                let start = path.len();
+                write!(path, "[{i}]").ok()?;
+                value(dumper, de, path)?;
+                path.truncate(start);
+            }
+            Some(())
+        })
+    }
+
+    /// Bounded by [crate::DecodeLimits::max_depth]
+    fn nested<'de>(
+        &mut self,
+        de: &mut Deserializer<'de, 'de>,
+        path: &mut String,
+        f: impl FnOnce(&mut Self, &mut Deserializer<'de, 'de>, &mut String) -> Option<()>,
+    ) -> Option<()> {
+        let max = de.options.limits.max_depth;
+        if de.depth >= max {
+            let limit = "nesting depth";
+            return self.fail(de.offset(), path, Error::LimitExceeded { limit, max });
+        }
+        de.depth += 1;
+        let result = f(self, de, path);
+        de.depth -= 1;
+        result
+    }
+}
+
+/// Value of a type without inner values
+fn scalar(
+    de: &mut Deserializer,
+    typ: &SmithType<usize>,
+    prog: &ResolvedSmithProgram,
+) -> Result<String, Error> {
+    Ok(match typ {
+        SmithType::String => format!("{:?}", de.read_str()?),
+        SmithType::Bytes => format!("{} bytes", de.read_bytes()?.len()),
+        SmithType::Timestamp => {
+            let (secs, nanos) = (de.read_dint()?, de.read_nanos()?);
+            wellknown::format_timestamp(secs, nanos)
+                .unwrap_or_else(|_| format!("{secs}s {nanos}ns"))
+        }
+        SmithType::Duration => {
+            let (secs, nanos) = (de.read_udint()?, de.read_nanos()?);
+            wellknown::format_duration(secs, nanos)?
+        }
+        SmithType::Uuid => wellknown::format_uuid(&de.read_n()?),
+        SmithType::Decimal => {
+            let mantissa = de.read_dint()?;
+            wellknown::format_decimal(mantissa, de.read()?)
+        }
+        //Numbers
+        _ => {
+            let seed = crate::value::ValueSeed { typ, prog };
+            match serde::de::DeserializeSeed::deserialize(seed, &mut *de)? {
+                SmithValue::F32(v) => v.to_string(),
+                SmithValue::F64(v) => v.to_string(),
+                SmithValue::Bool(v) => v.to_string(),
+                v => v.as_i64().map_or_else(
+                    || v.as_u64().unwrap_or_default().to_string(),
+                    |v| v.to_string(),
+                ),
+            }
+        }
+    })
+}
+
+/// Tag bits of a udInt / dInt starting with `first`, lowest bit last
+fn dynum_tag(first: u8) -> Option<String> {
+    let bits = match Dynum::encoded_len_from_tag(first).ok()? {
+        1 => 1,
+        2 => 2,
+        _ => 3,
+    };
+    Some(format!("{:0bits$b}", first & ((1 << bits) - 1)))
+}
+
+/// Schema syntax of `typ`
+fn type_name(typ: &SmithType<usize>, prog: &ResolvedSmithProgram) -> String {
+    match typ {
+        SmithType::I8 => "i8".to_owned(),
+        SmithType::I16 => "i16".to_owned(),
+        SmithType::I32 => "i32".to_owned(),
+        SmithType::F32 => "f32".to_owned(),
+        SmithType::F64 => "f64".to_owned(),
+        SmithType::U8 => "u8".to_owned(),
+        SmithType::U16 => "u16".to_owned(),
+        SmithType::U32 => "u32".to_owned(),
+        SmithType::U64 => "u64".to_owned(),
+        SmithType::UInt => "udInt".to_owned(),
+        SmithType::Int => "dInt".to_owned(),
+        SmithType::Bool => "bool".to_owned(),
+        SmithType::String => "string".to_owned(),
+        SmithType::Bytes => "bytes".to_owned(),
+        SmithType::Timestamp => "timestamp".to_owned(),
+        SmithType::Duration => "duration".to_owned(),
+        SmithType::Uuid => "uuid".to_owned(),
+        SmithType::Decimal => "decimal".to_owned(),
+        SmithType::CustomType(id, _) => prog.get(*id).map_or("?", |t| t.get_name()).to_owned(),
+        SmithType::Array(t) => format!("Array<{}>", type_name(t, prog)),
+        SmithType::FixedArray(t, len) => format!("[{};{len}]", type_name(t, prog)),
+        SmithType::Map(k, v) => format!("Map<{},{}>", type_name(k, prog), type_name(v, prog)),
+        SmithType::Option(t) => format!("Option<{}>", type_name(t, prog)),
+    }
+}
+
+impl Display for Dump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path_width = self.lines.iter().map(|l| l.path.len()).max().unwrap_or(0);
+        let typ_width = self.lines.iter().map(|l| l.typ.len()).max().unwrap_or(0);
+        for line in &self.lines {
+            let Range { start, end } = line.range;
+            let mut bytes = String::new();
+            for b in &self.data[start..end.min(start + SHOWN_BYTES)] {
+                write!(bytes, "{b:02x} ")?;
+            }
+            if end - start > SHOWN_BYTES {
+                bytes.push_str("..");
+            }
+            let range = if start == end {
+                String::new()
+            } else {
+                format!("{start:>5}..{end:<5}")
+            };
+            let line = format!(
+                "{range:12} {bytes:26} {:path_width$}  {:typ_width$}  {}",
+                line.path, line.typ, line.value
+            );
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        if let Some(e) = &self.error {
+            let rest = self.data.get(e.offset..).unwrap_or_default();
+            let mut bytes = String::new();
+            for b in rest.iter().take(SHOWN_BYTES) {
+                write!(bytes, "{b:02x} ")?;
+            }
+            let range = format!("{:>5}..{:<5}", e.offset, self.data.len());
+            writeln!(f, "{range:12} {bytes:26} {}  error: {}", e.path, e.error)?;
+            writeln!(f, "{} byte(s) not decoded", rest.len())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//! Pull parser - walks a message against its schema type and yields [Event]s without
+//! building values, see [crate::Smith::events]
+use serde::de::{Deserializer as _, Visitor};
+
+use super::{Deserializer, Result};
+use crate::generics_engine::SmithCustomTypVariant;
+use crate::smith_serde::Error;
+use crate::SmithType;
+
+/// One step through a message. Structs, arrays and maps are closed by [Event::End]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'de> {
+    /// Name of the struct, its fields follow
+    StructStart(&'de str),
+    /// Name of the next struct field, its value follows
+    Field(&'de str),
+    /// Variant of an enum, the payload value follows for variants with one
+    EnumVariant(&'de str),
+    /// Number of elements, the elements follow
+    ArrayStart(usize),
+    /// Number of entries, key and value of every entry follow
+    MapStart(usize),
+    Scalar(Scalar<'de>),
+    /// Absent `Option` - present options are transparent, their value follows directly
+    None,
+    End,
+}
+
+/// Value of a schema type without inner values, strings and bytes are borrowed from the message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar<'de> {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    UInt(u64),
+    Int(i64),
+    Str(&'de str),
+    Bytes(&'de [u8]),
+    Timestamp {
+        secs: i64,
+        nanos: u32,
+    },
+    Duration {
+        secs: u64,
+        nanos: u32,
+    },
+    Uuid([u8; 16]),
+    Decimal {
+        mantissa: i64,
+        scale: u8,
+    },
+    /// Bitmask of a flags type, the first flag is the lowest bit
+    Flags(u64),
+}
+
+/// Open struct, array or map
+enum Frame<'de> {
+    Struct(&'de [(String, SmithType<usize>)]),
+    Seq(&'de SmithType<usize>, usize),
+    //Remaining entries, the key of the current entry was read
+    Map(&'de SmithType<usize>, &'de SmithType<usize>, usize, bool),
+}
+
+/// Iterator over the [Event]s of one message. Stops after the first error,
+/// trailing bytes after the message are reported as error
+pub struct EventReader<'de> {
+    de: Deserializer<'de, 'de>,
+    stack: Vec<Frame<'de>>,
+    //Value read by the next event, the root type before the first
+    pending: Option<&'de SmithType<usize>>,
+    done: bool,
+}
+
+impl<'de> EventReader<'de> {
+    /// Reads a message of the current type of `de`, the header has to be read already
+    pub fn new(de: Deserializer<'de, 'de>) -> Self {
+        let root = de.current_type;
+        Self {
+            de,
+            stack: Vec::new(),
+            pending: Some(root),
+            done: false,
+        }
+    }
+
+    /// Position of the next event in the message
+    pub fn offset(&self) -> usize {
+        self.de.offset()
+    }
+
+    /// Nesting of the next event, 0 for the root value
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event<'de>>> {
+        if let Some(typ) = self.pending.take() {
+            return self.value(typ).map(Some);
+        }
+        let event = match self.stack.last_mut() {
+            None => {
+                self.de.end()?;
+                return Ok(None);
+            }
+            Some(Frame::Struct(fields)) if !fields.is_empty() => {
+                let all = *fields;
+                *fields = &all[1..];
+                self.pending = Some(&all[0].1);
+                Event::Field(&all[0].0)
+            }
+            Some(Frame::Seq(elemtyp, remaining)) if *remaining > 0 => {
+                *remaining -= 1;
+                let elemtyp = *elemtyp;
+                return self.value(elemtyp).map(Some);
+            }
+            Some(Frame::Map(keytyp, valtyp, remaining, key_read)) if *remaining > 0 => {
+                let typ = if *key_read {
+                    *remaining -= 1;
+                    valtyp
+                } else {
+                    keytyp
+                };
+                *key_read = !*key_read;
+                let typ = *typ;
+                return self.value(typ).map(Some);
+            }
+            Some(_) => {
+                self.stack.pop();
+                Event::End
+            }
+        };
+        Ok(Some(event))
+    }
+
+    /// Starts reading a value of `typ`
+    fn value(&mut self, typ: &'de SmithType<usize>) -> Result<Event<'de>> {
+        let de = &mut self.de;
+        de.current_type = typ;
+        let scalar = match typ {
+            SmithType::UInt => Scalar::UInt(de.read_udint()?),
+            SmithType::Int => Scalar::Int(de.read_dint()?),
+            SmithType::String => Scalar::Str(de.read_str()?),
+            SmithType::Bytes => Scalar::Bytes(de.read_bytes()?),
+            SmithType::Timestamp => Scalar::Timestamp {
+                secs: de.read_dint()?,
+                nanos: de.read_nanos()?,
+            },
+            SmithType::Duration => Scalar::Duration {
+                secs: de.read_udint()?,
+                nanos: de.read_nanos()?,
+            },
+            SmithType::Uuid => Scalar::Uuid(de.read_n()?),
+            SmithType::Decimal => Scalar::Decimal {
+                mantissa: de.read_dint()?,
+                scale: de.read()?,
+            },
+            SmithType::Option(inner) => {
+                if !de.read_bool()? {
+                    return Ok(Event::None);
+                }
+                return self.value(inner);
+            }
+            SmithType::Array(elemtyp) => {
+                //Chunks of arrays with chunk table follow the table in order
+                let len = de.read_array_len(elemtyp)?;
+                return self.open(Frame::Seq(elemtyp, len), Event::ArrayStart(len));
+            }
+            SmithType::FixedArray(elemtyp, len) => {
+                return self.open(Frame::Seq(elemtyp, *len), Event::ArrayStart(*len));
+            }
+            SmithType::Map(keytyp, valtyp) => {
+                let len = de.read_udint()?;
+                let len = de.check_elements(len, &[keytyp, valtyp])?;
+                return self.open(Frame::Map(keytyp, valtyp, len, false), Event::MapStart(len));
+            }
+            SmithType::CustomType(id, _) => {
+                let custom = de.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+                match &custom.variant {
+                    SmithCustomTypVariant::Struct(s) => {
+                        return self.open(Frame::Struct(&s.fields), Event::StructStart(&s.name));
+                    }
+                    SmithCustomTypVariant::Enum(_) => {
+                        let variant = de.current_variant()?;
+                        self.pending = variant.1.as_ref();
+                        return Ok(Event::EnumVariant(&variant.0));
+                    }
+                    SmithCustomTypVariant::Flags(f) => Scalar::Flags(de.read_flags(f)?),
+                }
+            }
+            //Fixed-width numbers and bool
+            _ => de.deserialize_any(ScalarVisitor)?,
+        };
+        Ok(Event::Scalar(scalar))
+    }
+
+    fn open(&mut self, frame: Frame<'de>, event: Event<'de>) -> Result<Event<'de>> {
+        let max = self.de.options.limits.max_depth;
+        if self.stack.len() >= max {
+            return Err(Error::LimitExceeded {
+                limit: "nesting depth",
+                max,
+            });
+        }
+        self.stack.push(frame);
+        Ok(event)
+    }
+}
+
+impl<'de> Iterator for EventReader<'de> {
+    type Item = Result<Event<'de>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let offset = self.offset();
+        let event = self.next_event().map_err(|e| e.within("", offset));
+        self.done = !matches!(event, Ok(Some(_)));
+        event.transpose()
+    }
+}
+
+struct ScalarVisitor;
+
+impl<'de> Visitor<'de> for ScalarVisitor {
+    type Value = Scalar<'de>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a number or bool")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::Bool(v))
+    }
+
+    fn visit_i8<E>(self, v: i8) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::I8(v))
+    }
+
+    fn visit_i16<E>(self, v: i16) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::I16(v))
+    }
+
+    fn visit_i32<E>(self, v: i32) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::I32(v))
+    }
+
+    fn visit_u8<E>(self, v: u8) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::U8(v))
+    }
+
+    fn visit_u16<E>(self, v: u16) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::U16(v))
+    }
+
+    fn visit_u32<E>(self, v: u32) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::U32(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::U64(v))
+    }
+
+    fn visit_f32<E>(self, v: f32) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::F32(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<Scalar<'de>, E> {
+        Ok(Scalar::F64(v))
+    }
+}
@@ -1,16 +1,47 @@
+use std::ops::Range;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::{Deserialize, forward_to_deserialize_any};
+use serde::de::value::SeqDeserializer;
 use serde::de::{
     self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
     Visitor,
 };
 
-use crate::generics_engine::SmithStruct;
+use crate::format::{is_header_byte, DecodeOptions, Endianness, StringEncoding, WireFormat};
+use crate::generics_engine::{SmithFlags, SmithStruct};
 use crate::resolver::ResolvedSmithProgram;
-use crate::smith_serde::{Error, Result};
+use crate::smith_serde::{root_name, Error, Result};
 use crate::smith_serde::Error::StrToCharError;
-use crate::SmithType;
+use crate::{par, size, wellknown, SmithType};
+
+mod dump;
+mod events;
+mod skip;
+mod stream;
+pub use dump::{Dump, DumpError, DumpLine};
+pub use events::{Event, EventReader, Scalar};
+pub use skip::ValidationReport;
+pub use stream::{Decoded, StreamDecoder};
 
 type Data<'a> = &'a [u8];
+/// `(elements, bytes)` of every chunk of a chunked array
+type Chunks<'a> = Vec<(usize, Data<'a>)>;
+
+/// Reads a fixed-width number in the byte order of the wire format
+macro_rules! fixed {
+    ($Self:ident, $T:ty) => {{
+        let bytes = $Self.read_n()?;
+        match $Self.format.endianness {
+            Endianness::Big => <$T>::from_be_bytes(bytes),
+            Endianness::Little => <$T>::from_le_bytes(bytes),
+        }
+    }};
+}
 
 struct BufferIter<'b, 'a> {
     buff: &'a mut &'b [u8],
@@ -36,55 +67,150 @@ enum EnumRepr {
     Rust,
 }
 
-pub struct Deserializer<'de> {
+/// Reads serde values from the binary representation. `'de` is the lifetime of the data,
+/// `'s` the one of the schema
+pub struct Deserializer<'de, 's> {
     data: Data<'de>,
-    prog: &'de ResolvedSmithProgram,
-    current_type: &'de SmithType<usize>,
+    prog: &'s ResolvedSmithProgram,
+    current_type: &'s SmithType<usize>,
     enum_repr: EnumRepr,
+    format: WireFormat,
+    options: DecodeOptions,
+    //Nesting of the value being decoded and array elements read so far, see DecodeLimits
+    depth: usize,
+    elements: usize,
+    //Offset of the end of `data` in the message, see `offset`
+    end: usize,
+    //Program handed to the workers decoding chunked arrays, see `with_shared_program`
+    shared_prog: Option<Arc<ResolvedSmithProgram>>,
 }
 
-impl<'de> Deserializer<'de> {
+impl<'de, 's> Deserializer<'de, 's> {
     pub fn from_bytes(
         data: Data<'de>,
-        prog: &'de ResolvedSmithProgram,
-        current_type: &'de SmithType<usize>,
+        prog: &'s ResolvedSmithProgram,
+        current_type: &'s SmithType<usize>,
     ) -> Self {
         Self {
             data,
             prog,
             current_type,
             enum_repr: EnumRepr::Rust,
+            format: WireFormat::default(),
+            options: DecodeOptions::default(),
+            depth: 0,
+            elements: 0,
+            end: data.len(),
+            shared_prog: None,
         }
     }
 
     pub fn from_bytes_json(
         data: Data<'de>,
-        prog: &'de ResolvedSmithProgram,
-        current_type: &'de SmithType<usize>,
+        prog: &'s ResolvedSmithProgram,
+        current_type: &'s SmithType<usize>,
     ) -> Self {
         Self {
             data,
             prog,
             current_type,
             enum_repr: EnumRepr::Json,
+            format: WireFormat::default(),
+            options: DecodeOptions::default(),
+            depth: 0,
+            elements: 0,
+            end: data.len(),
+            shared_prog: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_options(mut self, options: DecodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Decodes with `prog`, which is shared with the workers decoding chunked arrays of
+    /// [par::deserialize] fields - without it those are decoded sequentially
+    pub fn with_shared_program(mut self, prog: &'s Arc<ResolvedSmithProgram>) -> Self {
+        self.prog = prog;
+        self.shared_prog = Some(prog.clone());
+        self
+    }
+
+    /// Fails if not all bytes were consumed
+    pub fn end(&self) -> Result<()> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::TrailingCharacters)
         }
     }
 
     #[inline(always)]
+    /// Verifies the header recorded for non-default wire formats, see [WireFormat::header].
+    /// Default readers skip [WireFormat::DEFAULT_HEADER] and reject any other header byte
+    pub fn read_header(&mut self) -> Result<()> {
+        let received = self.data.first().copied();
+        let Some(expected) = self.format.header() else {
+            match received {
+                Some(WireFormat::DEFAULT_HEADER) => {
+                    self.read()?;
+                }
+                Some(b) if is_header_byte(b) => {
+                    return Err(Error::WireFormatMismatch {
+                        expected: self.format,
+                        received: WireFormat::from_header(b),
+                    });
+                }
+                _ => {}
+            }
+            return Ok(());
+        };
+        if received.is_none() {
+            return Err(Error::EndOfStream { needed: 1 });
+        }
+        if received != Some(expected) {
+            return Err(Error::WireFormatMismatch {
+                expected: self.format,
+                received: received.and_then(WireFormat::from_header),
+            });
+        }
+        self.read()?;
+        Ok(())
+    }
+
     pub fn read(&mut self) -> Result<u8> {
         if let Some(t) = self.data.first() {
             let b = *t;
             self.data = &self.data[1..];
             Ok(b)
         } else {
-            Err(Error::EndOfStream)
+            Err(Error::EndOfStream { needed: 1 })
+        }
+    }
+
+    /// Bools and option presence bytes, anything but `0` and `1` is invalid
+    pub(crate) fn read_bool(&mut self) -> Result<bool> {
+        match self.read()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(Error::InvalidBool(b)),
         }
     }
 
     pub fn read_str(&mut self) -> Result<&'de str> {
+        if let StringEncoding::LengthPrefixed = self.format.strings {
+            return std::str::from_utf8(self.read_bytes()?).map_err(Error::UTF8Error);
+        }
         let mut i = 0;
         loop {
-            if *self.data.get(i).ok_or(Error::EndOfStream)? == 0 {
+            self.check_string_len(i)?;
+            if *self.data.get(i).ok_or(Error::EndOfStream { needed: 1 })? == 0 {
                 let s = std::str::from_utf8(&self.data[0..i]).map_err(|e| Error::UTF8Error(e));
                 self.data = &self.data[i + 1..];
                 return s;
@@ -93,22 +219,241 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    /// Remaining, not yet decoded bytes
+    pub fn remaining(&self) -> Data<'de> {
+        self.data
+    }
+
+    /// Position in the message, errors report it in [Error::Context]
+    pub fn offset(&self) -> usize {
+        self.end - self.data.len()
+    }
+
+    /// Fails with the amount of missing bytes if the next udInt / dInt is incomplete
+    #[inline(always)]
+    fn require_dynum(&self) -> Result<()> {
+        let first = *self.data.first().ok_or(Error::EndOfStream { needed: 1 })?;
+        let len = Dynum::encoded_len_from_tag(first).map_err(Error::DynumError)? as usize;
+        if self.data.len() < len {
+            return Err(Error::EndOfStream {
+                needed: len - self.data.len(),
+            });
+        }
+        Ok(())
+    }
+
     pub fn read_udint(&mut self) -> Result<u64> {
+        self.require_dynum()?;
         let mut buffiter = BufferIter {
             buff: &mut self.data,
         };
         Dynum::decode_binary_stream(&mut buffiter).map_err(Error::DynumError)
     }
 
+    pub fn read_dint(&mut self) -> Result<i64> {
+        self.require_dynum()?;
+        let mut buffiter = BufferIter {
+            buff: &mut self.data,
+        };
+        Dynum::decode_signed_binary_stream(&mut buffiter).map_err(Error::DynumError)
+    }
+
+    fn current_flags(&self) -> Option<&'s SmithFlags> {
+        match self.current_type {
+            SmithType::CustomType(id, _) => self.prog.get(*id)?.as_flags(),
+            _ => None,
+        }
+    }
+
+    fn read_flags(&mut self, flags: &SmithFlags) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes[..flags.byte_len()].copy_from_slice(self.read_slice(flags.byte_len())?);
+        let mask = u64::from_le_bytes(bytes);
+        if mask & !flags.all() != 0 {
+            return Err(Error::UnknownFlag(format!("{:#x}", mask & !flags.all())));
+        }
+        Ok(mask)
+    }
+
+    fn read_nanos(&mut self) -> Result<u32> {
+        let nanos = self.read_udint()?;
+        if nanos >= wellknown::NANOS_PER_SEC as u64 {
+            return Err(Error::InvalidWellKnown {
+                typ: "nanoseconds",
+                value: nanos.to_string(),
+            });
+        }
+        Ok(nanos as u32)
+    }
+
+    pub fn read_slice(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.data.len() < len {
+            return Err(Error::EndOfStream {
+                needed: len - self.data.len(),
+            });
+        }
+        let (slice, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(slice)
+    }
+
+    /// Reads a udInt length followed by as many bytes
+    pub fn read_bytes(&mut self) -> Result<&'de [u8]> {
+        let len = usize::try_from(self.read_udint()?).map_err(|_| Error::TryFromIntError)?;
+        self.check_string_len(len)?;
+        self.read_slice(len)
+    }
+
+    fn check_string_len(&self, len: usize) -> Result<()> {
+        let max = self.options.limits.max_string_len;
+        if len > max {
+            return Err(Error::LimitExceeded {
+                limit: "string length",
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Counts `len` elements towards [crate::DecodeLimits::max_array_elements] and rejects
+    /// lengths the remaining bytes can not hold, `elemtyps` make up one element
+    fn check_elements(&mut self, len: u64, elemtyps: &[&SmithType<usize>]) -> Result<usize> {
+        let len = usize::try_from(len).map_err(|_| Error::TryFromIntError)?;
+        let max = self.options.limits.max_array_elements;
+        self.elements = self.elements.saturating_add(len);
+        if self.elements > max {
+            return Err(Error::LimitExceeded {
+                limit: "array elements",
+                max,
+            });
+        }
+        //Most elements take at least a byte, the size analysis is only needed beyond that
+        if len > self.data.len() {
+            let min = elemtyps
+                .iter()
+                .map(|t| size::size_bounds(t, self.prog).min)
+                .fold(0, usize::saturating_add);
+            let needed = min.saturating_mul(len);
+            if needed > self.data.len() {
+                return Err(Error::EndOfStream {
+                    needed: needed - self.data.len(),
+                });
+            }
+        }
+        Ok(len)
+    }
+
+    /// Decodes a nested value with `f`, bounded by [crate::DecodeLimits::max_depth].
+    /// Errors of the outermost value get the name of its type in front of their path
+    fn nested<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let max = self.options.limits.max_depth;
+        if self.depth >= max {
+            return Err(Error::LimitExceeded {
+                limit: "nesting depth",
+                max,
+            });
+        }
+        let typ = self.current_type;
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        match value {
+            Err(e) if self.depth == 0 => Err(e.within(root_name(typ, self.prog), self.offset())),
+            value => value,
+        }
+    }
+
+    /// Reads the length of an `Array` - the chunk table of arrays encoded with one is skipped,
+    /// the elements follow it in order
+    pub fn read_array_len(&mut self, elemtyp: &SmithType<usize>) -> Result<usize> {
+        let len = self.read_udint()?;
+        if len != par::CHUNK_TABLE_MARKER {
+            return self.check_elements(len, &[elemtyp]);
+        }
+        let len = self.read_udint()?;
+        let chunks = self.read_udint()?;
+        for _ in 0..chunks {
+            self.read_udint()?;
+            self.read_udint()?;
+        }
+        self.check_elements(len, &[elemtyp])
+    }
+
+    /// Reads the chunk table and the chunks of an array encoded with one - `None` (and nothing
+    /// consumed) for arrays without chunk table.
+    /// Returns `(elements, bytes)` of every chunk, the elements add up to the array length
+    fn read_chunk_table(&mut self, elemtyp: &SmithType<usize>) -> Result<Option<Chunks<'de>>> {
+        let start = self.data;
+        if self.read_udint()? != par::CHUNK_TABLE_MARKER {
+            self.data = start;
+            return Ok(None);
+        }
+        let len = self.read_udint()?;
+        let len = self.check_elements(len, &[elemtyp])?;
+        let count = usize::try_from(self.read_udint()?).map_err(|_| Error::TryFromIntError)?;
+        //Every table entry takes at least 2 bytes
+        let mut table = Vec::with_capacity(count.min(self.data.len() / 2));
+        for _ in 0..count {
+            let elements = self.read_udint()?;
+            let bytes = self.read_udint()?;
+            table.push((
+                usize::try_from(elements).map_err(|_| Error::TryFromIntError)?,
+                usize::try_from(bytes).map_err(|_| Error::TryFromIntError)?,
+            ));
+        }
+        let total = table
+            .iter()
+            .try_fold(0usize, |total, (elements, _)| total.checked_add(*elements));
+        if total != Some(len) {
+            return Err(Error::Static("Chunk table does not match the array length"));
+        }
+        let chunks = table
+            .into_iter()
+            .map(|(elements, bytes)| Ok((elements, self.read_slice(bytes)?)))
+            .collect::<Result<_>>()?;
+        Ok(Some(chunks))
+    }
+
     pub fn read_n<const N: usize>(&mut self) -> Result<[u8; N]> {
         let mut buff = [0; N];
-        for i in 0..N {
-            buff[i] = self.read()?
-        }
+        buff.copy_from_slice(self.read_slice(N)?);
         Ok(buff)
     }
 }
 
+/// Decodes one chunk of a chunked array, all bytes have to be consumed.
+/// `elements` are the indices of the chunk elements in the array, `offset` the start of the chunk
+pub(crate) fn decode_chunk<'de, 's, T>(
+    chunk: Data<'de>,
+    offset: usize,
+    elements: Range<usize>,
+    prog: &'s Arc<ResolvedSmithProgram>,
+    elemtyp: &'s SmithType<usize>,
+    format: WireFormat,
+    options: DecodeOptions,
+) -> Result<Vec<T>>
+where
+    T: Deserialize<'de>,
+{
+    let mut deserializer = Deserializer::from_bytes(chunk, prog, elemtyp)
+        .with_shared_program(prog)
+        .with_format(format)
+        .with_options(options);
+    deserializer.end = offset + chunk.len();
+    //Elements are nested in the array
+    deserializer.depth = 1;
+    //`elements` is checked against the array length only, not the chunk
+    let mut out = Vec::with_capacity(elements.len().min(chunk.len()));
+    for i in elements {
+        deserializer.current_type = elemtyp;
+        let value = T::deserialize(&mut deserializer)
+            .map_err(|e| e.within(format_args!("[{i}]"), deserializer.offset()))?;
+        out.push(value);
+    }
+    deserializer.end()?;
+    Ok(out)
+}
+
 pub fn from_bytes<'a, T>(
     data: Data<'a>,
     prog: &'a ResolvedSmithProgram,
@@ -119,20 +464,17 @@ where
 {
     let mut deserializer = Deserializer::from_bytes(data, prog, current_type);
     let t = T::deserialize(&mut deserializer)?;
-    if deserializer.data.len() == 0 {
-        Ok(t)
-    } else {
-        Err(Error::TrailingCharacters)
-    }
+    deserializer.end()?;
+    Ok(t)
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de, 's, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de, 's> {
     type Error = Error;
 
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64
-        bytes byte_buf option unit unit_struct newtype_struct tuple_struct
-        tuple identifier ignored_any seq
+        tuple_struct
+        identifier ignored_any
     }
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -140,18 +482,23 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         match self.current_type {
-            SmithType::I8 => visitor.visit_i8(i8::from_be_bytes(self.read_n()?)),
-            SmithType::I16 => visitor.visit_i16(i16::from_be_bytes(self.read_n()?)),
-            SmithType::I32 => visitor.visit_i32(i32::from_be_bytes(self.read_n()?)),
-            SmithType::F32 => visitor.visit_f32(f32::from_be_bytes(self.read_n()?)),
-            SmithType::F64 => visitor.visit_f64(f64::from_be_bytes(self.read_n()?)),
-            SmithType::U8 => visitor.visit_u8(u8::from_be_bytes(self.read_n()?)),
-            SmithType::U16 => visitor.visit_u16(u16::from_be_bytes(self.read_n()?)),
-            SmithType::U32 => visitor.visit_u32(u32::from_be_bytes(self.read_n()?)),
-            SmithType::U64 => visitor.visit_u64(u64::from_be_bytes(self.read_n()?)),
+            SmithType::I8 => visitor.visit_i8(fixed!(self, i8)),
+            SmithType::I16 => visitor.visit_i16(fixed!(self, i16)),
+            SmithType::I32 => visitor.visit_i32(fixed!(self, i32)),
+            SmithType::F32 => visitor.visit_f32(fixed!(self, f32)),
+            SmithType::F64 => visitor.visit_f64(fixed!(self, f64)),
+            SmithType::U8 => visitor.visit_u8(fixed!(self, u8)),
+            SmithType::U16 => visitor.visit_u16(fixed!(self, u16)),
+            SmithType::U32 => visitor.visit_u32(fixed!(self, u32)),
+            SmithType::U64 => visitor.visit_u64(fixed!(self, u64)),
             SmithType::UInt => visitor.visit_u64(self.read_udint()?),
-            SmithType::Bool => visitor.visit_bool(u8::from_be(self.read()?) != 0),
+            SmithType::Int => visitor.visit_i64(self.read_dint()?),
+            SmithType::Bool => visitor.visit_bool(self.read_bool()?),
             SmithType::String => self.deserialize_str(visitor),
+            SmithType::Bytes => match self.enum_repr {
+                EnumRepr::Json => visitor.visit_string(BASE64.encode(self.read_bytes()?)),
+                EnumRepr::Rust => visitor.visit_borrowed_bytes(self.read_bytes()?),
+            },
             SmithType::CustomType(id, _) => {
                 let t = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
                 match &t.variant {
@@ -161,17 +508,134 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                     crate::generics_engine::SmithCustomTypVariant::Enum(_e) => {
                         return self.deserialize_enum("", &[], visitor);
                     }
+                    //Json shows the names, rust types (bitflags) take the bits
+                    crate::generics_engine::SmithCustomTypVariant::Flags(f) => {
+                        let mask = self.read_flags(f)?;
+                        match self.enum_repr {
+                            EnumRepr::Json => visitor.visit_seq(flag_names(f, mask)),
+                            EnumRepr::Rust => visitor.visit_u64(mask),
+                        }
+                    }
                 }
             }
             SmithType::Array(t) => {
-                let len = self.read_udint()?;
+                let len = self.read_array_len(t)?;
+                self.nested(|de| {
+                    visitor.visit_seq(SeqVisitor {
+                        de,
+                        eltyp: t,
+                        remaining: len,
+                        index: 0,
+                    })
+                })
+            }
+            SmithType::FixedArray(t, len) => self.nested(|de| {
                 visitor.visit_seq(SeqVisitor {
-                    de: self,
+                    de,
                     eltyp: t,
-                    remaining: len as usize,
+                    remaining: *len,
+                    index: 0,
                 })
+            }),
+            SmithType::Map(_, _) => self.deserialize_map(visitor),
+            SmithType::Option(_) => self.deserialize_option(visitor),
+            SmithType::Timestamp => {
+                let secs = self.read_dint()?;
+                let nanos = self.read_nanos()?;
+                visitor.visit_string(wellknown::format_timestamp(secs, nanos)?)
+            }
+            SmithType::Duration => match self.enum_repr {
+                EnumRepr::Json => {
+                    let secs = self.read_udint()?;
+                    let nanos = self.read_nanos()?;
+                    visitor.visit_string(wellknown::format_duration(secs, nanos)?)
+                }
+                EnumRepr::Rust => self.deserialize_map(visitor),
+            },
+            SmithType::Uuid => visitor.visit_string(wellknown::format_uuid(&self.read_n()?)),
+            SmithType::Decimal => {
+                let mantissa = self.read_dint()?;
+                let scale = self.read()?;
+                visitor.visit_string(wellknown::format_decimal(mantissa, scale))
+            }
+        }
+    }
+
+    /// Reads the presence byte of an `Option<T>` schema type. Other schema types are always present
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let SmithType::Option(inner) = self.current_type {
+            if !self.read_bool()? {
+                return visitor.visit_none();
             }
+            self.current_type = inner;
         }
+        visitor.visit_some(self)
+    }
+
+    /// Unit and unit structs are only valid for structs without fields - they take zero bytes
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let SmithType::CustomType(id, _) = self.current_type {
+            let typ = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+            if typ.as_struct().is_some_and(|s| s.fields.is_empty()) {
+                return visitor.visit_unit();
+            }
+        }
+        Err(Error::MissmatchedType2 {
+            expected: "struct without fields".to_owned(),
+            received: self.current_type.clone(),
+        })
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    /// Newtypes are transparent - the inner value is read as the schema type
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        //Chunked array requested by `par::deserialize` - the visitor decodes the chunks
+        if let (par::TOKEN, true, EnumRepr::Rust, SmithType::Array(elemtyp), Some(prog)) = (
+            _name,
+            self.options.parallel,
+            self.enum_repr,
+            self.current_type,
+            &self.shared_prog,
+        ) {
+            let prog = prog.clone();
+            let start = self.data;
+            if let Some(chunks) = self.read_chunk_table(elemtyp)? {
+                //The chunks directly precede the remaining data
+                let len = chunks.iter().map(|(_, c)| c.len()).sum::<usize>();
+                let table = start.len() - self.data.len() - len;
+                let error = Rc::new(RefCell::new(None));
+                let context = par::DecodeContext {
+                    prog,
+                    elemtyp: (**elemtyp).clone(),
+                    format: self.format,
+                    options: self.options,
+                    offset: self.offset() - len,
+                    chunks: chunks.iter().map(|(elements, c)| (*elements, c.len())).collect(),
+                    error: error.clone(),
+                };
+                let value = {
+                    let _guard = par::enter_decode(context);
+                    visitor.visit_borrowed_bytes(&start[table..table + len])
+                };
+                return value.map_err(|e| error.take().unwrap_or(e));
+            }
+        }
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
@@ -196,10 +660,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let SmithType::String = self.current_type {
-            visitor.visit_borrowed_str(self.read_str()?)
-        } else {
-            Err(Error::Expected(SmithType::String))
+        match self.current_type {
+            SmithType::String => visitor.visit_borrowed_str(self.read_str()?),
+            SmithType::Timestamp | SmithType::Uuid | SmithType::Decimal => {
+                self.deserialize_any(visitor)
+            }
+            //Flags as string: "Read | Write"
+            SmithType::CustomType(..) => match self.current_flags() {
+                Some(flags) => {
+                    let mask = self.read_flags(flags)?;
+                    visitor.visit_string(flags.names(mask).collect::<Vec<_>>().join(" | "))
+                }
+                None => Err(Error::Expected(SmithType::String)),
+            },
+            _ => Err(Error::Expected(SmithType::String)),
         }
     }
 
@@ -207,10 +681,83 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if let SmithType::String = self.current_type {
-            visitor.visit_str(self.read_str()?)
+        match self.current_type {
+            SmithType::String => visitor.visit_str(self.read_str()?),
+            SmithType::Timestamp | SmithType::Uuid | SmithType::Decimal => {
+                self.deserialize_any(visitor)
+            }
+            //Flags as string: "Read | Write"
+            SmithType::CustomType(..) => match self.current_flags() {
+                Some(flags) => {
+                    let mask = self.read_flags(flags)?;
+                    visitor.visit_string(flags.names(mask).collect::<Vec<_>>().join(" | "))
+                }
+                None => Err(Error::Expected(SmithType::String)),
+            },
+            _ => Err(Error::Expected(SmithType::String)),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.current_type {
+            SmithType::Bytes => visitor.visit_borrowed_bytes(self.read_bytes()?),
+            SmithType::Array(t) if **t == SmithType::U8 => {
+                let len = self.read_array_len(t)?;
+                visitor.visit_borrowed_bytes(self.read_slice(len)?)
+            }
+            SmithType::FixedArray(t, len) if **t == SmithType::U8 => {
+                visitor.visit_borrowed_bytes(self.read_slice(*len)?)
+            }
+            SmithType::Uuid => visitor.visit_borrowed_bytes(self.read_slice(16)?),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Vec<u8> & co request a sequence - bytes share the layout of Array<u8>
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(flags) = self.current_flags() {
+            let mask = self.read_flags(flags)?;
+            visitor.visit_seq(flag_names(flags, mask))
+        } else if let SmithType::Bytes = self.current_type {
+            let len = usize::try_from(self.read_udint()?).map_err(|_| Error::TryFromIntError)?;
+            self.check_string_len(len)?;
+            visitor.visit_seq(SeqVisitor {
+                de: self,
+                eltyp: &SmithType::U8,
+                remaining: len,
+                index: 0,
+            })
         } else {
-            Err(Error::Expected(SmithType::String))
+            self.deserialize_any(visitor)
+        }
+    }
+
+    /// Rust arrays ([T; N]) are deserialized as tuple by serde
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.current_type {
+            SmithType::FixedArray(_, fixed_len) if *fixed_len != len => {
+                Err(Error::FixedArrayLenMismatch {
+                    expected: *fixed_len,
+                    received: len,
+                })
+            }
+            _ => self.deserialize_any(visitor),
         }
     }
 
@@ -218,6 +765,26 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        if let SmithType::Map(keytyp, valtyp) = self.current_type {
+            let len = self.read_udint()?;
+            let len = self.check_elements(len, &[keytyp, valtyp])?;
+            return self.nested(|de| {
+                visitor.visit_map(MapVisitor {
+                    de,
+                    keytyp,
+                    valtyp,
+                    remaining: len,
+                    index: 0,
+                })
+            });
+        }
+        if let Some(structyp) = wellknown::struct_of(self.current_type) {
+            return visitor.visit_map(StructVisitor {
+                de: self,
+                structyp,
+                current_field_idx: 0,
+            });
+        }
         if let SmithType::CustomType(id, _) = self.current_type {
             let s = self
                 .prog
@@ -228,10 +795,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                     expected: "struct".to_owned(),
                     received: "enum",
                 })?;
-            return visitor.visit_map(StructVisitor {
-                de: self,
-                structyp: s,
-                current_field_idx: 0,
+            return self.nested(|de| {
+                visitor.visit_map(StructVisitor {
+                    de,
+                    structyp: s,
+                    current_field_idx: 0,
+                })
             });
         } else {
             return Err(Error::MissmatchedType2 {
@@ -274,20 +843,32 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 })?;
 
             let varid = self.read_udint()?;
-            let variant = &s.variants[varid as usize];
+            let variant = usize::try_from(varid)
+                .ok()
+                .and_then(|i| s.variants.get(i))
+                .ok_or_else(|| Error::EnumVariantOutOfRange {
+                    enumeration: s.name.clone(),
+                    index: varid,
+                })?;
 
-            if _name.len() > 0 && _variants[varid as usize] != variant.0 {
-                return Err(Error::EnumVariantWrongName {
-                    expected: _variants[varid as usize].to_owned(),
-                    received: variant.0.to_owned(),
-                });
+            if _name.len() > 0 {
+                //The schema variant exists, the rust enum may still be shorter
+                let expected = _variants.get(varid as usize).copied().unwrap_or_default();
+                if expected != variant.0 {
+                    return Err(Error::EnumVariantWrongName {
+                        expected: expected.to_owned(),
+                        received: variant.0.to_owned(),
+                    });
+                }
             }
             let repr = self.enum_repr;
-            let evisit = EnumVisitor { de: self, variant };
-            match repr {
-                EnumRepr::Json => visitor.visit_map(EnumAsMapVisitor(evisit, 0)),
-                EnumRepr::Rust => visitor.visit_enum(evisit),
-            }
+            self.nested(|de| {
+                let evisit = EnumVisitor { de, variant };
+                match repr {
+                    EnumRepr::Json => visitor.visit_map(EnumAsMapVisitor(evisit, 0)),
+                    EnumRepr::Rust => visitor.visit_enum(evisit),
+                }
+            })
         } else {
             return Err(Error::MissmatchedType {
                 expected: "struct".to_owned(),
@@ -297,13 +878,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-struct StructVisitor<'a, 'de: 'a> {
-    pub de: &'a mut Deserializer<'de>,
-    pub structyp: &'de SmithStruct<usize>,
+fn flag_names(
+    flags: &SmithFlags,
+    mask: u64,
+) -> SeqDeserializer<impl Iterator<Item = &str>, Error> {
+    SeqDeserializer::new(flags.names(mask))
+}
+
+struct StructVisitor<'a, 'de: 'a, 's> {
+    pub de: &'a mut Deserializer<'de, 's>,
+    pub structyp: &'s SmithStruct<usize>,
     pub current_field_idx: usize,
 }
 
-impl<'de, 'a> MapAccess<'de> for StructVisitor<'a, 'de> {
+impl<'de, 's, 'a> MapAccess<'de> for StructVisitor<'a, 'de, 's> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, Self::Error>
@@ -324,17 +912,60 @@ impl<'de, 'a> MapAccess<'de> for StructVisitor<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
+        let field = self.current_field_idx.checked_sub(1);
+        seed.deserialize(&mut *self.de).map_err(|e| {
+            let name = field.and_then(|i| self.structyp.fields.get(i)).map_or("", |f| &f.0);
+            e.within(format_args!(".{name}"), self.de.offset())
+        })
+    }
+}
+
+struct MapVisitor<'a, 'de: 'a, 's> {
+    pub de: &'a mut Deserializer<'de, 's>,
+    pub keytyp: &'s SmithType<usize>,
+    pub valtyp: &'s SmithType<usize>,
+    pub remaining: usize,
+    //Index of the current entry
+    pub index: usize,
+}
+
+impl<'de, 's, 'a> MapAccess<'de> for MapVisitor<'a, 'de, 's> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.de.current_type = self.keytyp;
+        seed.deserialize(&mut *self.de)
+            .map(Some)
+            .map_err(|e| e.within(format_args!("[{}]", self.index), self.de.offset()))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.de.current_type = self.valtyp;
+        self.index += 1;
         seed.deserialize(&mut *self.de)
+            .map_err(|e| e.within(format_args!("[{}]", self.index - 1), self.de.offset()))
     }
 }
 
-struct SeqVisitor<'a, 'de: 'a> {
-    pub de: &'a mut Deserializer<'de>,
-    pub eltyp: &'de SmithType<usize>,
+struct SeqVisitor<'a, 'de: 'a, 's> {
+    pub de: &'a mut Deserializer<'de, 's>,
+    pub eltyp: &'s SmithType<usize>,
     pub remaining: usize,
+    //Index of the next element
+    pub index: usize,
 }
 
-impl<'de, 'a> SeqAccess<'de> for SeqVisitor<'a, 'de> {
+impl<'de, 's, 'a> SeqAccess<'de> for SeqVisitor<'a, 'de, 's> {
     type Error = Error;
 
     fn next_element_seed<T>(
@@ -348,17 +979,20 @@ impl<'de, 'a> SeqAccess<'de> for SeqVisitor<'a, 'de> {
             return Ok(None);
         }
         self.remaining -= 1;
+        self.index += 1;
         self.de.current_type = self.eltyp;
-        seed.deserialize(&mut *self.de).map(Some)
+        seed.deserialize(&mut *self.de)
+            .map(Some)
+            .map_err(|e| e.within(format_args!("[{}]", self.index - 1), self.de.offset()))
     }
 }
 
-struct EnumVisitor<'a, 'de: 'a> {
-    pub de: &'a mut Deserializer<'de>,
-    pub variant: &'de (String, Option<SmithType<usize>>),
+struct EnumVisitor<'a, 'de: 'a, 's> {
+    pub de: &'a mut Deserializer<'de, 's>,
+    pub variant: &'s (String, Option<SmithType<usize>>),
 }
 
-impl<'de, 'a> EnumAccess<'de> for EnumVisitor<'a, 'de> {
+impl<'de, 's, 'a> EnumAccess<'de> for EnumVisitor<'a, 'de, 's> {
     type Error = Error;
 
     type Variant = Self;
@@ -376,9 +1010,15 @@ impl<'de, 'a> EnumAccess<'de> for EnumVisitor<'a, 'de> {
     }
 }
 
-struct EnumAsMapVisitor<'a, 'de: 'a>(EnumVisitor<'a, 'de>, u8);
+impl EnumVisitor<'_, '_, '_> {
+    fn in_payload(&self, e: Error) -> Error {
+        e.within(format_args!("<{}>", self.variant.0), self.de.offset())
+    }
+}
+
+struct EnumAsMapVisitor<'a, 'de: 'a, 's>(EnumVisitor<'a, 'de, 's>, u8);
 
-impl<'de, 'a> MapAccess<'de> for EnumAsMapVisitor<'a, 'de> {
+impl<'de, 's, 'a> MapAccess<'de> for EnumAsMapVisitor<'a, 'de, 's> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, Self::Error>
@@ -416,17 +1056,17 @@ impl<'de, 'a> MapAccess<'de> for EnumAsMapVisitor<'a, 'de> {
         } else if i == 1 {
             if let Some(s) = &self.0.variant.1 {
                 self.0.de.current_type = s;
-                seed.deserialize(&mut *self.0.de)
+                seed.deserialize(&mut *self.0.de).map_err(|e| self.0.in_payload(e))
             } else {
                 seed.deserialize(&mut NoneDeserializer)
             }
         } else {
-            panic!("{:?}", self.1);
+            Err(Error::Static("Enum value read after its payload"))
         }
     }
 }
 
-impl<'de, 'a> VariantAccess<'de> for EnumVisitor<'a, 'de> {
+impl<'de, 's, 'a> VariantAccess<'de> for EnumVisitor<'a, 'de, 's> {
     type Error = Error;
 
     fn unit_variant(self) -> std::result::Result<(), Self::Error> {
@@ -442,14 +1082,14 @@ impl<'de, 'a> VariantAccess<'de> for EnumVisitor<'a, 'de> {
             .1
             .as_ref()
             .ok_or(Error::Message(format!("enumvariant {} is not newtype", self.variant.0)))?;
-        seed.deserialize(&mut *self.de)
+        seed.deserialize(&mut *self.de).map_err(|e| self.in_payload(e))
     }
 
     fn tuple_variant<V>(self, _len: usize, _visitor: V) -> std::result::Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        unimplemented!("Smith does not support tuple variants for enums")
+        Err(Error::Static("Smith does not support tuple variants for enums"))
     }
 
     fn struct_variant<V>(
@@ -460,7 +1100,7 @@ impl<'de, 'a> VariantAccess<'de> for EnumVisitor<'a, 'de> {
     where
         V: Visitor<'de>,
     {
-        unimplemented!("Smith does not support struct variants for enums")
+        Err(Error::Static("Smith does not support struct variants for enums"))
     }
 }
 
@@ -471,13 +1111,14 @@ struct StringDeserializer<'a> {
     s: &'a str,
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut StringDeserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut StringDeserializer<'_> {
     type Error = Error;
     fn deserialize_any<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_borrowed_str(self.s)
+        //Names live in the schema, not the data
+        visitor.visit_str(self.s)
     }
 
     forward_to_deserialize_any! {
@@ -0,0 +1,390 @@
+//! Reading without decoding - skipping and validating values, seeking to a value by its path.
+//! See [crate::Smith::extract] and [crate::Smith::validate]
+use serde::de::DeserializeSeed;
+
+use std::ops::Range;
+
+use super::{Chunks, Deserializer, Result};
+use crate::format::StringEncoding;
+use crate::generics_engine::{SmithCustomTypVariant, SmithStruct};
+use crate::smith_serde::Error;
+use crate::value::{SmithValue, ValueSeed};
+use crate::{size, wellknown, SmithType};
+
+/// Result of [Deserializer::validate]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Bytes of the message up to the end of the value, including the format header
+    pub len: usize,
+    /// Elements of all arrays and entries of all maps in the value
+    pub elements: usize,
+}
+
+impl<'de, 's> Deserializer<'de, 's> {
+    /// Moves past the value of the current type without decoding it.
+    /// Only the structure is checked (lengths, enum variants, flags) - not the contents of
+    /// strings and numbers
+    pub fn skip(&mut self) -> Result<()> {
+        self.walk(false)
+    }
+
+    /// Checks the value of the current type without decoding it - everything [Self::skip]
+    /// checks and the contents: UTF-8 of strings, nanoseconds and the chunks of arrays with
+    /// chunk table. Bytes after the value are not checked
+    pub fn validate(&mut self) -> Result<ValidationReport> {
+        let elements = self.elements;
+        self.walk(true)?;
+        Ok(ValidationReport {
+            len: self.offset(),
+            elements: self.elements - elements,
+        })
+    }
+
+    /// Moves past the value of the current type, `strict` checks the contents as well
+    fn walk(&mut self, strict: bool) -> Result<()> {
+        let typ = self.current_type;
+        match typ {
+            SmithType::Bool if strict => self.read_bool().map(drop),
+            SmithType::I8 | SmithType::U8 | SmithType::Bool => self.skip_bytes(1),
+            SmithType::I16 | SmithType::U16 => self.skip_bytes(2),
+            SmithType::I32 | SmithType::U32 | SmithType::F32 => self.skip_bytes(4),
+            SmithType::U64 | SmithType::F64 => self.skip_bytes(8),
+            SmithType::Uuid => self.skip_bytes(16),
+            SmithType::UInt | SmithType::Int => self.skip_dynum(),
+            SmithType::Timestamp | SmithType::Duration if strict => {
+                self.skip_dynum()?;
+                self.read_nanos().map(drop)
+            }
+            SmithType::Timestamp | SmithType::Duration => {
+                self.skip_dynum()?;
+                self.skip_dynum()
+            }
+            SmithType::Decimal => {
+                self.skip_dynum()?;
+                self.skip_bytes(1)
+            }
+            SmithType::String if strict => self.read_str().map(drop),
+            SmithType::String => match self.format.strings {
+                StringEncoding::LengthPrefixed => self.read_bytes().map(drop),
+                StringEncoding::NulTerminated => {
+                    let max = self.options.limits.max_string_len;
+                    match self
+                        .data
+                        .iter()
+                        .take(max.saturating_add(1))
+                        .position(|b| *b == 0)
+                    {
+                        Some(len) => self.skip_bytes(len + 1),
+                        None if self.data.len() > max => Err(Error::LimitExceeded {
+                            limit: "string length",
+                            max,
+                        }),
+                        None => Err(Error::EndOfStream { needed: 1 }),
+                    }
+                }
+            },
+            SmithType::Bytes => self.read_bytes().map(drop),
+            SmithType::Option(inner) => {
+                if self.read_bool()? {
+                    self.current_type = inner;
+                    self.walk(strict)?;
+                }
+                Ok(())
+            }
+            SmithType::Array(elemtyp) => match self.read_chunk_table(elemtyp)? {
+                Some(chunks) if strict => self.walk_chunks(elemtyp, chunks),
+                //Chunks are read as a whole
+                Some(_) => Ok(()),
+                None => {
+                    let len = self.read_array_len(elemtyp)?;
+                    self.walk_elements(elemtyp, 0..len, strict)
+                }
+            },
+            SmithType::FixedArray(elemtyp, len) => self.walk_elements(elemtyp, 0..*len, strict),
+            SmithType::Map(keytyp, valtyp) => {
+                let len = self.read_udint()?;
+                let len = self.check_elements(len, &[keytyp, valtyp])?;
+                self.nested(|de| {
+                    for i in 0..len {
+                        de.current_type = keytyp;
+                        de.walk(strict)
+                            .and_then(|_| {
+                                de.current_type = valtyp;
+                                de.walk(strict)
+                            })
+                            .map_err(|e| e.within(format_args!("[{i}]"), de.offset()))?;
+                    }
+                    Ok(())
+                })
+            }
+            SmithType::CustomType(id, _) => {
+                let custom = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+                match &custom.variant {
+                    SmithCustomTypVariant::Struct(s) => self.nested(|de| {
+                        for (name, fieldtyp) in &s.fields {
+                            de.current_type = fieldtyp;
+                            de.walk(strict)
+                                .map_err(|e| e.within(format_args!(".{name}"), de.offset()))?;
+                        }
+                        Ok(())
+                    }),
+                    SmithCustomTypVariant::Enum(_) => match self.current_variant()? {
+                        (name, Some(payload)) => self.nested(|de| {
+                            de.current_type = payload;
+                            de.walk(strict)
+                                .map_err(|e| e.within(format_args!("<{name}>"), de.offset()))
+                        }),
+                        (_, None) => Ok(()),
+                    },
+                    SmithCustomTypVariant::Flags(f) => self.read_flags(f).map(drop),
+                }
+            }
+        }
+    }
+
+    /// Moves to the value at `path` below the current type and returns its type, the next value
+    /// read is that value. Segments are separated by `.`: struct fields, array indices and map
+    /// keys, `val` or the variant name for the payload of an enum. Options are transparent.
+    /// Returns `None` if this message does not contain the value (e.g. other enum variant,
+    /// missing option or index), paths the schema does not have fail with an error
+    pub fn seek(&mut self, path: &str) -> Result<Option<&'s SmithType<usize>>> {
+        let found = self.nested(|de| de.seek_path(path))?;
+        Ok(found.then_some(self.current_type))
+    }
+
+    fn seek_path(&mut self, path: &str) -> Result<bool> {
+        for segment in path.split('.').filter(|s| !s.is_empty()) {
+            while let SmithType::Option(inner) = self.current_type {
+                if !self.read_bool()? {
+                    return Ok(false);
+                }
+                self.current_type = inner;
+            }
+            let found = match self.current_type {
+                SmithType::Array(elemtyp) => self.seek_element(elemtyp, None, segment)?,
+                SmithType::FixedArray(elemtyp, len) => {
+                    self.seek_element(elemtyp, Some(*len), segment)?
+                }
+                SmithType::Map(keytyp, valtyp) => self.seek_entry(keytyp, valtyp, segment)?,
+                typ => match (wellknown::struct_of(typ), typ) {
+                    (Some(s), _) => self.seek_field(s, segment)?,
+                    (None, SmithType::CustomType(id, _)) => {
+                        let custom = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+                        match &custom.variant {
+                            SmithCustomTypVariant::Struct(s) => self.seek_field(s, segment)?,
+                            SmithCustomTypVariant::Enum(e) => {
+                                let known = segment == "val"
+                                    || e.variants.iter().any(|v| v.0 == segment && v.1.is_some());
+                                if !known {
+                                    return Err(Error::ValueNotHavingField(segment.to_owned()));
+                                }
+                                let variant = self.current_variant()?;
+                                match &variant.1 {
+                                    Some(payload) if segment == "val" || segment == variant.0 => {
+                                        self.current_type = payload;
+                                        true
+                                    }
+                                    _ => false,
+                                }
+                            }
+                            SmithCustomTypVariant::Flags(_) => {
+                                return Err(Error::ValueNotHavingField(segment.to_owned()))
+                            }
+                        }
+                    }
+                    _ => return Err(Error::ValueNotHavingField(segment.to_owned())),
+                },
+            };
+            if !found {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn skip_bytes(&mut self, len: usize) -> Result<()> {
+        self.read_slice(len).map(drop)
+    }
+
+    fn skip_dynum(&mut self) -> Result<()> {
+        self.require_dynum()?;
+        let len = Dynum::encoded_len_from_tag(self.data[0]).map_err(Error::DynumError)?;
+        self.skip_bytes(len as usize)
+    }
+
+    /// Elements of fixed size are skipped at once - when checking only those without invalid
+    /// values (numbers). `indices` of the elements in the array are used in errors
+    fn walk_elements(
+        &mut self,
+        elemtyp: &'s SmithType<usize>,
+        indices: Range<usize>,
+        strict: bool,
+    ) -> Result<()> {
+        let bounds = size::size_bounds(elemtyp, self.prog);
+        if bounds.is_fixed() && (!strict || any_bytes_valid(elemtyp)) {
+            let bytes = bounds
+                .min
+                .checked_mul(indices.len())
+                .ok_or(Error::TryFromIntError)?;
+            return self.skip_bytes(bytes);
+        }
+        self.nested(|de| {
+            for i in indices {
+                de.current_type = elemtyp;
+                de.walk(strict)
+                    .map_err(|e| e.within(format_args!("[{i}]"), de.offset()))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Checks every chunk of an array with chunk table holds its elements exactly
+    fn walk_chunks(&mut self, elemtyp: &'s SmithType<usize>, chunks: Chunks<'de>) -> Result<()> {
+        //The chunks directly precede the remaining data
+        let mut offset = self.offset() - chunks.iter().map(|(_, c)| c.len()).sum::<usize>();
+        let mut first = 0;
+        for (elements, chunk) in chunks {
+            let mut de = Deserializer::from_bytes(chunk, self.prog, elemtyp)
+                .with_format(self.format)
+                .with_options(self.options);
+            de.end = offset + chunk.len();
+            de.depth = self.depth;
+            de.elements = self.elements;
+            de.walk_elements(elemtyp, first..first + elements, true)?;
+            de.end()
+                .map_err(|e| e.within(format_args!("[{}]", first + elements), de.offset()))?;
+            self.elements = de.elements;
+            offset += chunk.len();
+            first += elements;
+        }
+        Ok(())
+    }
+
+    /// Reads the variant of the current enum type
+    pub(super) fn current_variant(&mut self) -> Result<&'s (String, Option<SmithType<usize>>)> {
+        let e = match self.current_type {
+            SmithType::CustomType(id, _) => self.prog.get(*id).and_then(|t| t.as_enum()),
+            _ => None,
+        }
+        .ok_or(Error::Expected(self.current_type.clone()))?;
+        let index = self.read_udint()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| e.variants.get(i))
+            .ok_or_else(|| Error::EnumVariantOutOfRange {
+                enumeration: e.name.clone(),
+                index,
+            })
+    }
+
+    fn seek_field(&mut self, structyp: &'s SmithStruct<usize>, name: &str) -> Result<bool> {
+        let index = structyp
+            .fields
+            .iter()
+            .position(|f| f.0 == name)
+            .ok_or_else(|| Error::ValueNotHavingField(name.to_owned()))?;
+        for (_, fieldtyp) in &structyp.fields[..index] {
+            self.current_type = fieldtyp;
+            self.skip()?;
+        }
+        self.current_type = &structyp.fields[index].1;
+        Ok(true)
+    }
+
+    /// Arrays with chunk table jump to the chunk of the element
+    fn seek_element(
+        &mut self,
+        elemtyp: &'s SmithType<usize>,
+        fixed_len: Option<usize>,
+        segment: &str,
+    ) -> Result<bool> {
+        let index: usize = segment
+            .parse()
+            .map_err(|_| Error::ValueNotHavingField(segment.to_owned()))?;
+        let (skip, len) = match fixed_len {
+            Some(len) => (index, len),
+            None => match self.read_chunk_table(elemtyp)? {
+                Some(chunks) => {
+                    let mut first = 0;
+                    let Some(i) = chunks.iter().position(|(elements, _)| {
+                        first += elements;
+                        first > index
+                    }) else {
+                        return Ok(false);
+                    };
+                    //The chunks precede the remaining data - continue inside the chunk,
+                    //the bytes after it are not needed
+                    let (elements, bytes) = chunks[i];
+                    let start =
+                        self.offset() - chunks[i..].iter().map(|c| c.1.len()).sum::<usize>();
+                    self.data = bytes;
+                    self.end = start + bytes.len();
+                    (index - (first - elements), elements)
+                }
+                None => (index, self.read_array_len(elemtyp)?),
+            },
+        };
+        if skip >= len {
+            return Ok(false);
+        }
+        self.walk_elements(elemtyp, 0..skip, false)?;
+        self.current_type = elemtyp;
+        Ok(true)
+    }
+
+    /// Map keys match by their string or number
+    fn seek_entry(
+        &mut self,
+        keytyp: &'s SmithType<usize>,
+        valtyp: &'s SmithType<usize>,
+        segment: &str,
+    ) -> Result<bool> {
+        let len = self.read_udint()?;
+        let len = self.check_elements(len, &[keytyp, valtyp])?;
+        for _ in 0..len {
+            self.current_type = keytyp;
+            let key = ValueSeed {
+                typ: keytyp,
+                prog: self.prog,
+            }
+            .deserialize(&mut *self)?;
+            self.current_type = valtyp;
+            if key_matches(&key, segment) {
+                return Ok(true);
+            }
+            self.skip()?;
+        }
+        Ok(false)
+    }
+}
+
+fn key_matches(key: &SmithValue, segment: &str) -> bool {
+    match key {
+        SmithValue::String(s) => s == segment,
+        key => match (key.as_i64(), key.as_u64()) {
+            (Some(n), _) => segment.parse() == Ok(n),
+            (_, Some(n)) => segment.parse() == Ok(n),
+            _ => false,
+        },
+    }
+}
+
+/// Fixed-size types every byte pattern is a valid value of
+fn any_bytes_valid(typ: &SmithType<usize>) -> bool {
+    match typ {
+        SmithType::FixedArray(elemtyp, _) => any_bytes_valid(elemtyp),
+        typ => matches!(
+            typ,
+            SmithType::I8
+                | SmithType::I16
+                | SmithType::I32
+                | SmithType::U8
+                | SmithType::U16
+                | SmithType::U32
+                | SmithType::U64
+                | SmithType::F32
+                | SmithType::F64
+                | SmithType::Uuid
+        ),
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{ErrorKind, Read};
+
+use serde::de::DeserializeOwned;
+
+use crate::smith_serde::{Error, Result};
+use crate::{Smith, SmithType};
+
+/// Smallest read from the underlying reader
+const MIN_READ: usize = 8 * 1024;
+
+/// Result of decoding a message from a buffer that may not contain all of it yet
+#[derive(Debug, PartialEq)]
+pub enum Decoded<T> {
+    /// The message and the amount of bytes it took, following bytes belong to the next message
+    Value { value: T, consumed: usize },
+    /// The buffer ends inside the message - at least this many more bytes are required
+    NeedMore(usize),
+}
+
+/// Decodes consecutive messages of one type from a reader (socket, file, pipe, ...).
+/// Bytes are read as they become available, bytes read past a message are kept for the next one.
+///
+/// The end of an incomplete message is found by skipping over the buffered bytes (see
+/// [super::Deserializer::skip]), the message is only decoded once it is complete.
+/// Every attempt skips from the start of the message again: a message arriving in `k` reads
+/// costs up to `k` skips over its bytes. Attempts wait for the minimum size of the type and
+/// for the missing bytes the previous attempt reported, so readers delivering large reads
+/// (files, buffered sockets) skip each message about once
+pub struct StreamDecoder<'a, R> {
+    smith: &'a Smith,
+    typ: &'a SmithType<usize>,
+    reader: R,
+    buff: Vec<u8>,
+    //Start of the next message in `buff`
+    pos: usize,
+    //Bytes of the next message required before it is skipped again
+    needed: usize,
+    //Minimum size of a message, see [crate::size]
+    min_len: usize,
+}
+
+impl<'a, R: Read> StreamDecoder<'a, R> {
+    pub fn new(smith: &'a Smith, typ: &'a SmithType<usize>, reader: R) -> Self {
+        let min_len = smith.size_bounds(typ).min.max(1);
+        Self {
+            smith,
+            typ,
+            reader,
+            buff: Vec::new(),
+            pos: 0,
+            needed: min_len,
+            min_len,
+        }
+    }
+
+    /// Reads the next message, `None` if the reader ended between two messages
+    pub fn next_message<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        loop {
+            let available = self.buff.len() - self.pos;
+            if available < self.needed {
+                let missing = self.needed - available;
+                if self.fill(missing)? < missing {
+                    return match self.buff.len() - self.pos {
+                        0 => Ok(None),
+                        available => Err(Error::EndOfStream {
+                            needed: self.needed - available,
+                        }),
+                    };
+                }
+                continue;
+            }
+            match self.message_len()? {
+                Decoded::Value { consumed, .. } => {
+                    let data = &self.buff[self.pos..self.pos + consumed];
+                    let value = self.smith.binary2rust(data, self.typ)?;
+                    self.pos += consumed;
+                    self.needed = self.min_len;
+                    return Ok(Some(value));
+                }
+                Decoded::NeedMore(needed) => self.needed = available + needed,
+            }
+        }
+    }
+
+    /// Length of the buffered message, skipped without decoding it
+    fn message_len(&self) -> Result<Decoded<()>> {
+        let mut deserializer = self.smith.deserializer_for(&self.buff[self.pos..], self.typ);
+        match deserializer.read_header().and_then(|_| deserializer.skip()) {
+            Ok(()) => Ok(Decoded::Value {
+                value: (),
+                consumed: deserializer.offset(),
+            }),
+            Err(e) => match e.root_cause() {
+                Error::EndOfStream { needed } => Ok(Decoded::NeedMore(*needed)),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Fails if there are bytes left after the last message
+    pub fn end(&mut self) -> Result<()> {
+        if self.pos < self.buff.len() || self.fill(1)? > 0 {
+            return Err(Error::TrailingCharacters);
+        }
+        Ok(())
+    }
+
+    /// Bytes read but not decoded yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buff[self.pos..]
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads at least `needed` bytes if available, returns 0 at the end of the reader
+    fn fill(&mut self, needed: usize) -> Result<usize> {
+        self.buff.drain(..self.pos);
+        self.pos = 0;
+        let start = self.buff.len();
+        let mut end = start;
+        while end - start < needed {
+            //Incomplete messages are skipped again from the start - grow geometrically.
+            //`needed` comes from the data, only allocate for bytes the reader provides
+            if end == self.buff.len() {
+                self.buff.resize(end + end.max(MIN_READ), 0);
+            }
+            match self.reader.read(&mut self.buff[end..]) {
+                Ok(0) => break,
+                Ok(n) => end += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.buff.truncate(end);
+                    return Err(Error::Io(e));
+                }
+            }
+        }
+        self.buff.truncate(end);
+        Ok(end - start)
+    }
+}
@@ -0,0 +1,253 @@
+/// Encoding of `string` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StringEncoding {
+    /// Utf-8 bytes followed by a `0` byte. Strings containing `\0` can't be encoded
+    #[default]
+    NulTerminated,
+    /// udInt length followed by the utf-8 bytes - allows any string and decodes without scanning
+    LengthPrefixed,
+}
+
+/// Byte order of fixed-width integers and floats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Big,
+    /// Native order of x86 and most ARM hosts - no byte swaps
+    Little,
+}
+
+/// Upper nibble of the format header, the lower nibble holds the format flags
+const HEADER_MAGIC: u8 = 0b1010_0000;
+const HEADER_LITTLE_ENDIAN: u8 = 0b0001;
+const HEADER_LENGTH_PREFIXED: u8 = 0b0010;
+
+/// Whether `byte` is in the range reserved for format headers.
+/// Messages of the default format whose first byte falls in this range are prefixed with
+/// [WireFormat::DEFAULT_HEADER], so the first byte always tells the formats apart
+pub(crate) fn is_header_byte(byte: u8) -> bool {
+    byte & 0b1111_0000 == HEADER_MAGIC
+}
+
+/// Options for the binary layout shared by serializer and deserializer.
+/// Both sides have to agree on the format, the default stays compatible to messages of older versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WireFormat {
+    pub strings: StringEncoding,
+    pub endianness: Endianness,
+}
+
+impl WireFormat {
+    pub fn with_strings(mut self, strings: StringEncoding) -> Self {
+        self.strings = strings;
+        self
+    }
+
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Non-default formats are recorded in a leading header byte, so readers expecting another
+    /// format fail instead of misreading the data.
+    /// The default format has no header to stay compatible to messages of older versions,
+    /// except for messages starting with a byte of the header range - see [is_header_byte]
+    pub fn header(&self) -> Option<u8> {
+        if *self == Self::default() {
+            return None;
+        }
+        let mut header = HEADER_MAGIC;
+        if self.endianness == Endianness::Little {
+            header |= HEADER_LITTLE_ENDIAN;
+        }
+        if self.strings == StringEncoding::LengthPrefixed {
+            header |= HEADER_LENGTH_PREFIXED;
+        }
+        Some(header)
+    }
+
+    /// Header of the default format, only written before messages starting with a header byte
+    pub(crate) const DEFAULT_HEADER: u8 = HEADER_MAGIC;
+
+    pub fn from_header(header: u8) -> Option<Self> {
+        if header & 0b1111_0000 != HEADER_MAGIC
+            || header & !(HEADER_MAGIC | HEADER_LITTLE_ENDIAN | HEADER_LENGTH_PREFIXED) != 0
+        {
+            return None;
+        }
+        Some(Self {
+            strings: if header & HEADER_LENGTH_PREFIXED != 0 {
+                StringEncoding::LengthPrefixed
+            } else {
+                StringEncoding::NulTerminated
+            },
+            endianness: if header & HEADER_LITTLE_ENDIAN != 0 {
+                Endianness::Little
+            } else {
+                Endianness::Big
+            },
+        })
+    }
+}
+
+/// How the serializer handles struct fields the value didn't provide
+/// (e.g. `#[serde(skip_serializing_if)]` or json objects with missing keys)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingFields {
+    /// Every field has to be present
+    Error,
+    /// Missing `Option` fields are encoded as `None`, other missing fields are an error
+    #[default]
+    OptionsAsNone,
+    /// Missing fields are encoded with the zero value of their type:
+    /// `0`, `false`, `""`, empty arrays and maps, `None`, the first enum variant, ...
+    Default,
+}
+
+/// How the serializer treats values that don't line up with the schema.
+/// Diagnostics are reported through the `log` crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncodeMode {
+    /// Enum variants have to match the schema name at the serde variant index,
+    /// unknown struct fields are an error
+    #[default]
+    Strict,
+    /// Enum variants are looked up by name (falling back to the serde variant index with a
+    /// warning), unknown struct fields are skipped with a warning
+    Lenient,
+}
+
+/// Thresholds for encoding arrays on the rayon thread pool, see [crate::par]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelOptions {
+    /// Shorter arrays are encoded sequentially - spawning tasks costs more than it saves
+    pub min_len: usize,
+    /// Elements per task, `None` splits the array evenly across the pool
+    pub chunk_size: Option<usize>,
+    /// Writes a table of the chunk sizes in front of parallel encoded arrays,
+    /// so they can be decoded in parallel as well - see [DecodeOptions::parallel]
+    pub chunk_table: bool,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            min_len: 1024,
+            chunk_size: None,
+            chunk_table: false,
+        }
+    }
+}
+
+impl ParallelOptions {
+    pub fn with_min_len(mut self, min_len: usize) -> Self {
+        self.min_len = min_len;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    pub fn with_chunk_table(mut self, chunk_table: bool) -> Self {
+        self.chunk_table = chunk_table;
+        self
+    }
+
+    /// Elements per task for an array of `len` elements - never zero
+    pub fn chunk_size_for(&self, len: usize) -> usize {
+        self.chunk_size
+            .unwrap_or_else(|| len.div_ceil(rayon::current_num_threads()))
+            .max(1)
+    }
+}
+
+/// Serializer-only options - unlike [WireFormat] they don't change how the output is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeOptions {
+    pub missing_fields: MissingFields,
+    pub mode: EncodeMode,
+    /// Parallel encoding of arrays wrapped in [crate::par::ParSlice], disabled by default
+    pub parallel: Option<ParallelOptions>,
+}
+
+impl EncodeOptions {
+    pub fn with_parallel(mut self, parallel: ParallelOptions) -> Self {
+        self.parallel = Some(parallel);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: EncodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_missing_fields(mut self, missing_fields: MissingFields) -> Self {
+        self.missing_fields = missing_fields;
+        self
+    }
+}
+
+/// Deserializer-only options
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DecodeOptions {
+    /// Decodes arrays written with a chunk table ([ParallelOptions::chunk_table]) on the
+    /// rayon thread pool, if the field asks for it with [crate::par].
+    /// Without it such arrays are read sequentially
+    pub parallel: bool,
+    pub limits: DecodeLimits,
+}
+
+impl DecodeOptions {
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Upper bounds for values read from untrusted data, exceeding one fails with
+/// `Error::LimitExceeded`.
+/// Array and map lengths are checked against the remaining bytes regardless, but arrays of
+/// zero sized types and strings are only bounded by these - tighten them for untrusted input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Nesting of structs, enums, arrays and maps - recursive types can nest without limit
+    pub max_depth: usize,
+    /// Elements of all arrays and entries of all maps of a message together
+    pub max_array_elements: usize,
+    /// Length of a single string or bytes value in bytes
+    pub max_string_len: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_array_elements: usize::MAX,
+            max_string_len: usize::MAX,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_array_elements(mut self, max_array_elements: usize) -> Self {
+        self.max_array_elements = max_array_elements;
+        self
+    }
+
+    pub fn with_max_string_len(mut self, max_string_len: usize) -> Self {
+        self.max_string_len = max_string_len;
+        self
+    }
+}
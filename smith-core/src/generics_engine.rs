@@ -19,10 +19,46 @@ pub struct SmithEnum<T> {
     pub name: String,
     pub variants: Vec<(String, Option<SmithType<T>>)>,
 }
+/// Set of named bits - encoded as bitmask of `ceil(flags / 8)` bytes, first flag is the lowest bit
+#[derive(Debug)]
+pub struct SmithFlags {
+    pub name: String,
+    pub flags: Vec<String>,
+}
+
+impl SmithFlags {
+    pub const MAX_FLAGS: usize = 64;
+
+    pub fn byte_len(&self) -> usize {
+        self.flags.len().div_ceil(8)
+    }
+
+    pub fn bit(&self, name: &str) -> Option<u64> {
+        self.flags.iter().position(|f| f == name).map(|i| 1 << i)
+    }
+
+    /// Mask with all declared flags set
+    pub fn all(&self) -> u64 {
+        match self.flags.len() {
+            Self::MAX_FLAGS => u64::MAX,
+            len => (1 << len) - 1,
+        }
+    }
+
+    pub fn names(&self, mask: u64) -> impl Iterator<Item = &str> {
+        self.flags
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| mask & (1 << i) != 0)
+            .map(|(_, f)| f.as_str())
+    }
+}
+
 #[derive(Debug)]
 pub enum SmithCustomTypVariant<T> {
     Struct(SmithStruct<T>),
     Enum(SmithEnum<T>),
+    Flags(SmithFlags),
 }
 
 #[derive(Debug)]
@@ -36,20 +72,28 @@ impl<T> SmithCustomTyp<T> {
         match &self.variant {
             SmithCustomTypVariant::Struct(s) => &s.name,
             SmithCustomTypVariant::Enum(e) => &e.name,
+            SmithCustomTypVariant::Flags(f) => &f.name,
         }
     }
 
     pub fn as_struct(&self) -> Option<&SmithStruct<T>> {
         match &self.variant {
             SmithCustomTypVariant::Struct(s) => Some(s),
-            SmithCustomTypVariant::Enum(_e) => None,
+            _ => None,
         }
     }
 
     pub fn as_enum(&self) -> Option<&SmithEnum<T>> {
         match &self.variant {
-            SmithCustomTypVariant::Struct(_s) => None,
             SmithCustomTypVariant::Enum(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub fn as_flags(&self) -> Option<&SmithFlags> {
+        match &self.variant {
+            SmithCustomTypVariant::Flags(f) => Some(f),
+            _ => None,
         }
     }
 }
@@ -65,6 +109,10 @@ impl From<&(Arc<AtomicUsize>, Rc<dyn RootDeclaration>)> for SmithCustomTyp<Strin
                 name: e.name.clone(),
                 variants: e.variants.clone(),
             }),
+            ASTRootType::Flags(f) => SmithCustomTypVariant::Flags(SmithFlags {
+                name: f.name.clone(),
+                flags: f.flags.clone(),
+            }),
         };
         Self {
             variant,
@@ -182,7 +230,13 @@ impl<'a> GenericEngine<'a> {
                     _ = imps.1.insert(blueprint.1.clone());
                 }
             }
-            SmithType::Array(gen) => self.do_typ(gen),
+            SmithType::Array(gen) | SmithType::FixedArray(gen, _) | SmithType::Option(gen) => {
+                self.do_typ(gen)
+            }
+            SmithType::Map(k, v) => {
+                self.do_typ(k);
+                self.do_typ(v);
+            }
             _ => {}
         }
     }
@@ -231,10 +285,16 @@ impl<'a> GenericEngine<'a> {
                     }
                 }
             }
-            SmithType::Array(t) => {
+            SmithType::Array(t) | SmithType::FixedArray(t, _) | SmithType::Option(t) => {
                 self.expand_field(t, blueprint, dep);
                 self.do_typ(t);
             }
+            SmithType::Map(k, v) => {
+                for t in [k, v] {
+                    self.expand_field(t, blueprint, dep);
+                    self.do_typ(t);
+                }
+            }
             _ => {}
         }
     }
@@ -7,11 +7,16 @@ use std::sync::Arc;
 use generics_engine::SmithCustomTyp;
 use serde::{Deserialize, Serialize};
 
+pub use format::{
+    DecodeLimits, DecodeOptions, EncodeMode, EncodeOptions, Endianness, MissingFields,
+    ParallelOptions, StringEncoding, WireFormat,
+};
 pub use generics_engine::SmithProgram;
 pub use parser::SmithType;
 
 use crate::resolver::ResolvedSmithProgram;
-use crate::smith_serde::Error;
+pub use crate::smith_serde::Error;
+pub use crate::value::SmithValue;
 
 pub mod generics_engine;
 pub mod parser;
@@ -19,8 +24,13 @@ pub mod resolver;
 mod utils;
 
 pub mod de;
+pub mod format;
+pub mod par;
 pub mod ser;
+pub mod size;
 mod smith_serde;
+pub mod value;
+pub mod wellknown;
 
 pub fn compile(src: &str) -> SmithProgram<usize> {
     let parsed = parser::parse(src);
@@ -29,29 +39,201 @@ pub fn compile(src: &str) -> SmithProgram<usize> {
 }
 
 #[derive(Clone)]
-pub struct Smith(Arc<ResolvedSmithProgram>);
+pub struct Smith {
+    prog: Arc<ResolvedSmithProgram>,
+    format: WireFormat,
+    encode_options: EncodeOptions,
+    decode_options: DecodeOptions,
+}
 
 impl Smith {
     pub fn get_type(&self, name: &str) -> Option<SmithType<usize>> {
-        self.0
+        self.prog
             .iter()
             .position(|e| e.get_name() == name)
             .map(|e| SmithType::CustomType(e, vec![]))
     }
 
     pub fn get_types(&self) -> &[SmithCustomTyp<usize>] {
-        (*self.0).as_slice()
+        (*self.prog).as_slice()
     }
 
     pub fn new(src: &str) -> Self {
-        Self(Arc::new(compile(src).expanded))
+        Self {
+            prog: Arc::new(compile(src).expanded),
+            format: WireFormat::default(),
+            encode_options: EncodeOptions::default(),
+            decode_options: DecodeOptions::default(),
+        }
+    }
+
+    /// Sets the wire format used for encoding and decoding - has to match the other side
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    /// Sets the options used for encoding, see [EncodeOptions]
+    pub fn with_encode_options(mut self, options: EncodeOptions) -> Self {
+        self.encode_options = options;
+        self
+    }
+
+    pub fn encode_options(&self) -> EncodeOptions {
+        self.encode_options
+    }
+
+    /// Sets the options used for decoding, see [DecodeOptions]
+    pub fn with_decode_options(mut self, options: DecodeOptions) -> Self {
+        self.decode_options = options;
+        self
+    }
+
+    pub fn decode_options(&self) -> DecodeOptions {
+        self.decode_options
+    }
+
+    /// Enables parallel encoding of arrays wrapped in [par::ParSlice] (or fields marked
+    /// `#[serde(with = "smith_core::par")]`)
+    pub fn with_parallel(mut self, parallel: ParallelOptions) -> Self {
+        self.encode_options.parallel = Some(parallel);
+        self
+    }
+
+    fn serializer_for<'a, W: std::io::Write>(
+        &'a self,
+        typ: &'a SmithType<usize>,
+        writer: W,
+    ) -> ser::Serializer<'a, W> {
+        ser::Serializer::from_writer(&self.prog, typ, writer)
+            .with_shared_program(&self.prog)
+            .with_format(self.format)
+            .with_options(self.encode_options)
+    }
+
+    fn deserializer_for<'a>(
+        &'a self,
+        data: &'a [u8],
+        typ: &'a SmithType<usize>,
+    ) -> de::Deserializer<'a, 'a> {
+        de::Deserializer::from_bytes(data, &self.prog, typ)
+            .with_shared_program(&self.prog)
+            .with_format(self.format)
+            .with_options(self.decode_options)
     }
 
     pub fn rust2binary<T>(&self, value: &T, typ: &SmithType<usize>) -> Result<Box<[u8]>, Error>
     where
         T: Serialize,
     {
-        ser::to_binary(value, &self.0, typ)
+        let mut serializer = self.serializer_for(typ, Vec::with_capacity(1048));
+        serializer.write_header()?;
+        value
+            .serialize(&mut serializer)
+            .map_err(|e| serializer.in_root(e, typ))?;
+        Ok(serializer.buffer().into_boxed_slice())
+    }
+
+    /// Appends the binary representation to `buff`, on errors `buff` is left unchanged.
+    /// Reusing the buffer (`clear()` between messages) keeps the steady state allocation free,
+    /// see [ser::Serializer::reset]
+    pub fn encode_into<T>(
+        &self,
+        value: &T,
+        typ: &SmithType<usize>,
+        buff: &mut Vec<u8>,
+    ) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        let len = buff.len();
+        self.to_writer(value, typ, &mut *buff).inspect_err(|_| buff.truncate(len))
+    }
+
+    /// Reusable serializer with the wire format of this instance, ready for the first message.
+    /// Call [ser::Serializer::reset] before each further message
+    pub fn serializer<'a>(&'a self, typ: &'a SmithType<usize>) -> ser::Serializer<'a> {
+        let mut serializer = self.serializer_for(typ, Vec::with_capacity(1048));
+        serializer.reset(typ);
+        serializer
+    }
+
+    /// Streams the binary representation into `writer` without buffering the whole message.
+    /// Unbuffered writers (files, sockets) should be wrapped in a `BufWriter`
+    pub fn to_writer<T, W>(&self, value: &T, typ: &SmithType<usize>, writer: W) -> Result<(), Error>
+    where
+        T: Serialize,
+        W: std::io::Write,
+    {
+        let mut serializer = self.serializer_for(typ, writer);
+        serializer.write_header()?;
+        value
+            .serialize(&mut serializer)
+            .map_err(|e| serializer.in_root(e, typ))
+    }
+
+    /// Exact length of the binary representation (including the format header),
+    /// computed by running the serializer against a [ser::ByteCounter] - it costs as much as
+    /// an encode, only the bytes aren't stored. Values that can't be encoded are errors.
+    /// [Smith::size_bounds] is free but doesn't look at the value: even for fixed-size types
+    /// default messages may take one more byte, see [WireFormat::header]
+    pub fn encoded_len<T>(&self, value: &T, typ: &SmithType<usize>) -> Result<usize, Error>
+    where
+        T: Serialize,
+    {
+        let mut counter = ser::ByteCounter::default();
+        self.to_writer(value, typ, &mut counter)?;
+        Ok(counter.0)
+    }
+
+    /// Static size bounds of `typ` (without the format header), see [size]
+    pub fn size_bounds(&self, typ: &SmithType<usize>) -> size::SizeBounds {
+        size::size_bounds(typ, &self.prog)
+    }
+
+    /// Decodes one message from the start of `data`, which may end before the message does
+    /// (e.g. bytes received so far). Returns how many more bytes are needed in that case
+    pub fn decode_partial<'a, T>(
+        &'a self,
+        data: &'a [u8],
+        typ: &'a SmithType<usize>,
+    ) -> Result<de::Decoded<T>, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        let mut deserializer = self.deserializer_for(data, typ);
+        let value = deserializer
+            .read_header()
+            .and_then(|_| T::deserialize(&mut deserializer));
+        match value {
+            Ok(value) => Ok(de::Decoded::Value {
+                value,
+                consumed: data.len() - deserializer.remaining().len(),
+            }),
+            Err(e) => match e.root_cause() {
+                Error::EndOfStream { needed } => Ok(de::Decoded::NeedMore(*needed)),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Decodes the single message `reader` contains, reading it as it becomes available.
+    /// Use [de::StreamDecoder] for readers with multiple messages
+    pub fn from_reader<T, R>(&self, reader: R, typ: &SmithType<usize>) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+        R: std::io::Read,
+    {
+        let mut decoder = de::StreamDecoder::new(self, typ, reader);
+        let value = decoder
+            .next_message()?
+            .ok_or(Error::EndOfStream { needed: 1 })?;
+        decoder.end()?;
+        Ok(value)
     }
 
     pub fn binary2rust<'a, T>(
@@ -62,29 +244,131 @@ impl Smith {
     where
         T: Deserialize<'a>,
     {
-        de::from_bytes(data, &self.0, typ)
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        let t = T::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(t)
+    }
+
+    /// Decodes only the value at `path` (e.g. `payload.val.items.0.amount`), everything before
+    /// it is skipped and everything after it left unread. `None` if the message does not contain
+    /// the value, see [de::Deserializer::seek] for the path syntax
+    pub fn extract(
+        &self,
+        data: &[u8],
+        typ: &SmithType<usize>,
+        path: &str,
+    ) -> Result<Option<SmithValue>, Error> {
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        let Some(typ) = deserializer.seek(path)? else {
+            return Ok(None);
+        };
+        let seed = value::ValueSeed {
+            typ,
+            prog: &self.prog,
+        };
+        serde::de::DeserializeSeed::deserialize(seed, &mut deserializer).map(Some)
+    }
+
+    /// [Smith::extract] into a rust type
+    pub fn extract_as<'a, T>(
+        &'a self,
+        data: &'a [u8],
+        typ: &'a SmithType<usize>,
+        path: &str,
+    ) -> Result<Option<T>, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        if deserializer.seek(path)?.is_none() {
+            return Ok(None);
+        }
+        T::deserialize(&mut deserializer).map(Some)
+    }
+
+    /// Checks `data` is exactly one well-formed message without decoding it: lengths, enum
+    /// variants, flags, udInt / dInt tags, UTF-8 and no trailing bytes. Honors the decode limits
+    pub fn validate(
+        &self,
+        data: &[u8],
+        typ: &SmithType<usize>,
+    ) -> Result<de::ValidationReport, Error> {
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        let report = deserializer.validate()?;
+        deserializer
+            .end()
+            .map_err(|e| e.within("", deserializer.offset()))?;
+        Ok(report)
+    }
+
+    /// Annotates every byte range of the message in `data` with its path, schema type and value.
+    /// Corrupt messages are dumped up to where decoding failed, see [de::Dump::error]
+    pub fn dump<'a>(&'a self, data: &'a [u8], typ: &'a SmithType<usize>) -> de::Dump<'a> {
+        let mut deserializer = self.deserializer_for(data, typ);
+        match deserializer.read_header() {
+            Ok(()) => de::Dump::read(deserializer, data),
+            Err(error) => de::Dump {
+                data,
+                lines: Vec::new(),
+                error: Some(de::DumpError {
+                    offset: 0,
+                    path: String::new(),
+                    error,
+                }),
+            },
+        }
+    }
+
+    /// Pull parser over the message in `data`, see [de::Event]
+    pub fn events<'a>(
+        &'a self,
+        data: &'a [u8],
+        typ: &'a SmithType<usize>,
+    ) -> Result<de::EventReader<'a>, Error> {
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        Ok(de::EventReader::new(deserializer))
+    }
+
+    /// Decodes without a rust type, the [SmithValue] follows the schema of `typ`
+    pub fn decode_value(&self, data: &[u8], typ: &SmithType<usize>) -> Result<SmithValue, Error> {
+        let mut deserializer = self.deserializer_for(data, typ);
+        deserializer.read_header()?;
+        let seed = value::ValueSeed {
+            typ,
+            prog: &self.prog,
+        };
+        let value = serde::de::DeserializeSeed::deserialize(seed, &mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    }
+
+    /// Encodes a [SmithValue] as `typ` - numbers are converted to the schema types
+    pub fn encode_value(
+        &self,
+        value: &SmithValue,
+        typ: &SmithType<usize>,
+    ) -> Result<Box<[u8]>, Error> {
+        self.rust2binary(value, typ)
     }
 }
 
 impl Smith {
+    /// Encodes json (json5) as `typ`. The json is read into a [SmithValue] first, so errors keep
+    /// the path and offset of [Smith::encode_value]
     pub fn json2binary(&self, json: &str, typ: &SmithType<usize>) -> Result<Box<[u8]>, String> {
-        let mut ser = ser::Serializer::new(&self.0, typ);
-        serde_transcode::transcode(
-            &mut json5::Deserializer::from_str(json).map_err(|e| e.to_string())?,
-            &mut ser,
-        )
-        .map_err(|e| format!("{e:?}"))?;
-
-        Ok(ser.buffer().into_boxed_slice())
+        let value: SmithValue = json5::from_str(json).map_err(|e| e.to_string())?;
+        self.encode_value(&value, typ).map_err(|e| e.to_string())
     }
+    /// Json layout of [Smith::decode_value]
     pub fn binary2json(&self, bin: &[u8], typ: &SmithType<usize>) -> Result<String, String> {
-        let mut buf = Vec::new();
-        serde_transcode::transcode(
-            &mut de::Deserializer::from_bytes_json(&bin, &self.0, typ),
-            &mut serde_json::Serializer::new(&mut buf),
-        )
-        .map_err(|e| format!("{e:?}"))?;
-        Ok(String::from_utf8(buf).map_err(|e| e.to_string())?)
+        let value = self.decode_value(bin, typ).map_err(|e| e.to_string())?;
+        serde_json::to_string(&value).map_err(|e| e.to_string())
     }
 }
 
@@ -149,6 +433,1358 @@ mod test {
         assert_eq!(res, BIN.to_vec().into_boxed_slice());
     }
 
+    #[test]
+    fn test_dint() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Delta {
+            dx: i64,
+            dy: i64,
+        }
+        let s = Smith::new("struct Delta{\n dx: dInt\n dy: dInt\n}");
+        let typ = s.get_type("Delta").unwrap();
+        let val = Delta { dx: -1, dy: 1 << 40 };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        assert_eq!(bin[0], 0b11);
+        assert_eq!(s.binary2rust::<Delta>(&bin, &typ).unwrap(), val);
+        assert_eq!(
+            s.binary2json(&bin, &typ).unwrap(),
+            r#"{"dx":-1,"dy":1099511627776}"#
+        );
+    }
+    #[test]
+    fn test_bytes() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Blob<'a> {
+            #[serde(with = "serde_bytes")]
+            hash: Vec<u8>,
+            data: &'a [u8],
+            raw: Vec<u8>,
+        }
+        let s = Smith::new("struct Blob{\n hash: bytes\n data: bytes\n raw: bytes\n}");
+        let typ = s.get_type("Blob").unwrap();
+        let val = Blob {
+            hash: vec![1, 2],
+            data: &[0, 0, 0],
+            raw: vec![],
+        };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        assert_eq!(&*bin, &[5, 1, 2, 7, 0, 0, 0, 1]);
+        assert_eq!(s.binary2rust::<Blob>(&bin, &typ).unwrap(), val);
+
+        let json = s.binary2json(&bin, &typ).unwrap();
+        assert_eq!(json, r#"{"hash":"AQI=","data":"AAAA","raw":""}"#);
+        assert_eq!(s.json2binary(&json, &typ).unwrap(), bin);
+    }
+    #[test]
+    fn test_map() {
+        use std::collections::{BTreeMap, HashMap};
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Inventory {
+            stock: HashMap<String, u32>,
+            names: BTreeMap<u8, String>,
+        }
+        let s = Smith::new("struct Inventory{\n stock: Map<string,u32>\n names: Map<u8,string>\n}");
+        let typ = s.get_type("Inventory").unwrap();
+        let val = Inventory {
+            stock: HashMap::from([("apple".to_owned(), 3)]),
+            names: BTreeMap::from([(1, "a".to_owned()), (2, "b".to_owned())]),
+        };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        assert_eq!(s.binary2rust::<Inventory>(&bin, &typ).unwrap(), val);
+
+        let json = s.binary2json(&bin, &typ).unwrap();
+        assert_eq!(json, r#"{"stock":{"apple":3},"names":{"1":"a","2":"b"}}"#);
+        assert_eq!(s.json2binary(&json, &typ).unwrap(), bin);
+
+        //Arrays of [key, value] pairs are accepted as well
+        let pairs = r#"{"stock":[["apple",3]],"names":[[1,"a"],[2,"b"]]}"#;
+        assert_eq!(s.json2binary(pairs, &typ).unwrap(), bin);
+        let err = s
+            .json2binary(r#"{"stock":[["apple",3,4]],"names":[]}"#, &typ)
+            .unwrap_err();
+        assert!(err.starts_with("Inventory.stock[0]"), "{err}");
+        //Entries that aren't pairs are rejected instead of written as they are
+        for entries in [r#"[{"a":1}]"#, "[1]", r#"[["apple"]]"#] {
+            let json = format!(r#"{{"stock":{entries},"names":[]}}"#);
+            let err = s.json2binary(&json, &typ).unwrap_err();
+            assert!(err.starts_with("Inventory.stock[0]"), "{err}");
+        }
+        let err = s.json2binary(r#"{"stock":[],"names":[{"a":1}]}"#, &typ).unwrap_err();
+        assert!(err.contains("expected [key, value] pair, received map"), "{err}");
+        assert!(s.json2binary(r#"{"stock":[],"names":[1]}"#, &typ).is_err());
+    }
+    #[test]
+    fn test_option() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Profile {
+            name: String,
+            nick: Option<String>,
+            age: Option<u8>,
+        }
+        let s = Smith::new("struct Profile{\n name: string\n nick: Option<string>\n age: Option<u8>\n}");
+        let typ = s.get_type("Profile").unwrap();
+        let val = Profile {
+            name: "a".to_owned(),
+            nick: None,
+            age: Some(3),
+        };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        assert_eq!(&*bin, &[b'a', 0, 0, 1, 3]);
+        assert_eq!(s.binary2rust::<Profile>(&bin, &typ).unwrap(), val);
+
+        let json = s.binary2json(&bin, &typ).unwrap();
+        assert_eq!(json, r#"{"name":"a","nick":null,"age":3}"#);
+        assert_eq!(s.json2binary(&json, &typ).unwrap(), bin);
+    }
+    #[test]
+    fn test_fixed_array() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Key {
+            hash: [u8; 4],
+            matrix: [[i8; 2]; 2],
+        }
+        let s = Smith::new("struct Key{\n hash: [u8; 4]\n matrix: [[i8;2]; 2]\n}");
+        let typ = s.get_type("Key").unwrap();
+        let val = Key {
+            hash: [1, 2, 3, 4],
+            matrix: [[1, -1], [-1, 1]],
+        };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        assert_eq!(&*bin, &[1, 2, 3, 4, 1, 255, 255, 1]);
+        assert_eq!(s.binary2rust::<Key>(&bin, &typ).unwrap(), val);
+
+        let json = s.binary2json(&bin, &typ).unwrap();
+        assert_eq!(json, r#"{"hash":[1,2,3,4],"matrix":[[1,-1],[-1,1]]}"#);
+        assert_eq!(s.json2binary(&json, &typ).unwrap(), bin);
+        assert!(s
+            .json2binary(r#"{"hash":[1,2,3],"matrix":[[1,-1],[-1,1]]}"#, &typ)
+            .is_err());
+    }
+    #[test]
+    fn test_length_prefixed_strings() {
+        let schema = "struct Person{\n name: string\n age: u8\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Person").unwrap();
+        assert!(s.json2binary(r#"{"name":"a\u0000b","age":1}"#, &typ).is_err());
+
+        let s = s.with_format(WireFormat::default().with_strings(StringEncoding::LengthPrefixed));
+        let bin = s.json2binary(r#"{"name":"a\u0000b","age":1}"#, &typ).unwrap();
+        //Header byte records the non-default format
+        assert_eq!(&*bin, &[0b1010_0010, 7, b'a', 0, b'b', 1]);
+        assert_eq!(
+            s.binary2json(&bin, &typ).unwrap(),
+            r#"{"name":"a\u0000b","age":1}"#
+        );
+        assert!(s.binary2json(&bin[..3], &typ).is_err());
+    }
+    #[test]
+    fn test_wellknown_types() {
+        let schema =
+            "struct Event{\n at: timestamp\n took: duration\n id: uuid\n price: decimal\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Event").unwrap();
+        let json = r#"{"at":"2023-04-01T12:30:00.250Z","took":"PT90.5S","id":"67e55044-10b1-426f-9247-bb680e5fe0c8","price":"-12.50"}"#;
+        let bin = s.json2binary(json, &typ).unwrap();
+        assert_eq!(s.binary2json(&bin, &typ).unwrap(), json);
+
+        //Alternative input notations are normalized
+        let other = r#"{"at":"2023-04-01T14:30:00.25+02:00","took":"PT1M30.5S","id":"67E5504410B1426F9247BB680E5FE0C8","price":-12.5}"#;
+        let bin2 = s.json2binary(other, &typ).unwrap();
+        assert_eq!(
+            s.binary2json(&bin2, &typ).unwrap(),
+            json.replace("-12.50", "-12.5")
+        );
+        assert!(s.json2binary(&json.replace("PT90.5S", "P1M"), &typ).is_err());
+        assert!(s.json2binary(&json.replace("-12.50", "1.2.3"), &typ).is_err());
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Event {
+            at: chrono::DateTime<chrono::Utc>,
+            took: std::time::Duration,
+            id: String,
+            price: String,
+        }
+        let event: Event = s.binary2rust(&bin, &typ).unwrap();
+        assert_eq!(event.took, std::time::Duration::from_millis(90_500));
+        assert_eq!(event.at.timestamp_millis(), 1680352200250);
+        assert_eq!(&*s.rust2binary(&event, &typ).unwrap(), &*bin);
+    }
+    #[test]
+    fn test_flags() {
+        let schema = "flags Permissions { Read Write Exec }\n\
+                      struct File{\n name: string\n perms: Permissions\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("File").unwrap();
+        let bin = s.json2binary(r#"{"name":"a","perms":["Read","Exec"]}"#, &typ).unwrap();
+        assert_eq!(&*bin, &[b'a', 0, 0b101]);
+        assert_eq!(
+            s.binary2json(&bin, &typ).unwrap(),
+            r#"{"name":"a","perms":["Read","Exec"]}"#
+        );
+        assert!(s.json2binary(r#"{"name":"a","perms":["Delete"]}"#, &typ).is_err());
+        assert!(s.binary2json(&[b'a', 0, 0b1000], &typ).is_err());
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+        #[serde(transparent)]
+        struct Permissions(u8);
+        bitflags::bitflags! {
+            impl Permissions: u8 {
+                const READ = 1 << 0;
+                const WRITE = 1 << 1;
+                const EXEC = 1 << 2;
+            }
+        }
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct File<P> {
+            name: String,
+            perms: P,
+        }
+        let file: File<Permissions> = s.binary2rust(&bin, &typ).unwrap();
+        assert_eq!(file.perms, Permissions::READ | Permissions::EXEC);
+        assert_eq!(&*s.rust2binary(&file, &typ).unwrap(), &*bin);
+
+        let file: File<Vec<String>> = s.binary2rust(&bin, &typ).unwrap();
+        assert_eq!(file.perms, ["Read", "Exec"]);
+        assert_eq!(&*s.rust2binary(&file, &typ).unwrap(), &*bin);
+
+        let file = File { name: "a".to_owned(), perms: "Read | Exec" };
+        assert_eq!(&*s.rust2binary(&file, &typ).unwrap(), &*bin);
+    }
+    #[test]
+    fn test_unit_and_newtype_structs() {
+        let schema = "struct Marker{}\nstruct Tagged{\n id: udInt\n marker: Marker\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Tagged").unwrap();
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct UserId(u64);
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Marker;
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Empty {}
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tagged<I, M> {
+            id: I,
+            marker: M,
+        }
+
+        let val = Tagged { id: UserId(300), marker: Marker };
+        let bin = s.rust2binary(&val, &typ).unwrap();
+        let plain = s.rust2binary(&Tagged { id: 300u64, marker: () }, &typ).unwrap();
+        assert_eq!(bin, plain);
+        assert_eq!(bin.len(), 2);
+        assert_eq!(s.binary2rust::<Tagged<UserId, Marker>>(&bin, &typ).unwrap(), val);
+        assert_eq!(
+            s.binary2rust::<Tagged<u64, Empty>>(&bin, &typ).unwrap(),
+            Tagged { id: 300, marker: Empty {} }
+        );
+        assert_eq!(s.binary2json(&bin, &typ).unwrap(), r#"{"id":300,"marker":{}}"#);
+
+        //Units are only valid for structs without fields
+        assert!(s.rust2binary(&Tagged { id: (), marker: Marker }, &typ).is_err());
+        assert!(s.binary2rust::<Tagged<(), Marker>>(&bin, &typ).is_err());
+        let typ = s.get_type("Marker").unwrap();
+        assert_eq!(s.rust2binary(&Marker, &typ).unwrap().len(), 0);
+    }
+    #[test]
+    fn test_endianness() {
+        let schema = "struct Point{\n x: i16\n y: f32\n id: u8\n}";
+        let big = Smith::new(schema);
+        let typ = big.get_type("Point").unwrap();
+        let little = big
+            .clone()
+            .with_format(WireFormat::default().with_endianness(Endianness::Little));
+        let json = r#"{"x":-2,"y":1.5,"id":3}"#;
+
+        let bin = big.json2binary(json, &typ).unwrap();
+        assert_eq!(&*bin, &[0xff, 0xfe, 0x3f, 0xc0, 0, 0, 3]);
+        let bin = little.json2binary(json, &typ).unwrap();
+        assert_eq!(&*bin, &[0b1010_0001, 0xfe, 0xff, 0, 0, 0xc0, 0x3f, 3]);
+        assert_eq!(little.binary2json(&bin, &typ).unwrap(), json);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Point {
+            x: i16,
+            y: f32,
+            id: u8,
+        }
+        let p: Point = little.binary2rust(&bin, &typ).unwrap();
+        assert_eq!(p, Point { x: -2, y: 1.5, id: 3 });
+        assert_eq!(little.rust2binary(&p, &typ).unwrap(), bin);
+
+        //Mismatched readers fail instead of misreading the numbers
+        let err = little.binary2rust::<Point>(&big.rust2binary(&p, &typ).unwrap(), &typ);
+        assert!(matches!(err, Err(Error::WireFormatMismatch { received: None, .. })));
+        let lp = WireFormat::default().with_strings(StringEncoding::LengthPrefixed);
+        let err = big.clone().with_format(lp).binary2rust::<Point>(&bin, &typ);
+        assert!(matches!(
+            err,
+            Err(Error::WireFormatMismatch { received: Some(f), .. }) if f == little.format()
+        ));
+
+        //Default readers reject other headers, whatever the root type starts with
+        let s = Smith::new("struct A{\n a: u32\n}");
+        let typ = s.get_type("A").unwrap();
+        let little = s
+            .clone()
+            .with_format(WireFormat::default().with_endianness(Endianness::Little));
+        let bin = little.json2binary(r#"{"a":7}"#, &typ).unwrap();
+        assert_eq!(&*bin, &[0b1010_0001, 7, 0, 0, 0]);
+        assert!(matches!(
+            s.binary2rust::<serde_json::Value>(&bin, &typ),
+            Err(Error::WireFormatMismatch { received: Some(f), .. }) if f == little.format()
+        ));
+        let err = s.validate(&bin, &typ).unwrap_err();
+        assert!(matches!(err, Error::WireFormatMismatch { received: Some(_), .. }));
+        assert!(s.binary2json(&bin, &typ).unwrap_err().starts_with("wire format mismatch"));
+        let err = s.binary2rust::<serde_json::Value>(&[0b1010_1000, 0, 0, 0, 7], &typ);
+        assert!(matches!(err, Err(Error::WireFormatMismatch { received: None, .. })));
+
+        //Default messages starting with a header byte get the default header
+        let bin = s.json2binary(r#"{"a":2684354560}"#, &typ).unwrap();
+        assert_eq!(&*bin, &[0b1010_0000, 0xa0, 0, 0, 0]);
+        let value = serde_json::json!({"a": 2684354560u32});
+        assert_eq!(s.encoded_len(&value, &typ).unwrap(), bin.len());
+        assert_eq!(s.binary2json(&bin, &typ).unwrap(), r#"{"a":2684354560}"#);
+        assert!(matches!(
+            little.binary2rust::<serde_json::Value>(&bin, &typ),
+            Err(Error::WireFormatMismatch { received: Some(f), .. }) if f == s.format()
+        ));
+        let bin = s.json2binary(r#"{"a":1}"#, &typ).unwrap();
+        assert_eq!(&*bin, &[0, 0, 0, 1]);
+    }
+    #[test]
+    fn test_to_writer() {
+        let s = Smith::new(SCHEMA);
+        let typ = s.get_type("Packet").unwrap();
+        let packet: Types::Packet = s.binary2rust(BIN, &typ).unwrap();
+
+        let mut out = Vec::new();
+        s.to_writer(&packet, &typ, &mut out).unwrap();
+        assert_eq!(out, BIN);
+
+        //Maps without known length are buffered, the rest goes straight to the writer
+        let s = Smith::new("struct Counts{\n counts: Map<string,u8>\n name: string\n}");
+        let typ = s.get_type("Counts").unwrap();
+        let json = r#"{"name":"a","counts":{"b":1}}"#;
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let mut out = std::io::Cursor::new(Vec::new());
+        s.to_writer(&value, &typ, &mut out).unwrap();
+        assert_eq!(out.into_inner(), s.rust2binary(&value, &typ).unwrap().to_vec());
+
+        struct Full;
+        impl std::io::Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WriteZero.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let err = s.to_writer(&value, &typ, Full).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::Io(_)));
+
+        //Failed encodes leave the buffer as it was
+        let mut buff = vec![7];
+        s.encode_into(&value, &typ, &mut buff).unwrap();
+        let encoded = buff.clone();
+        let invalid = serde_json::json!({"name":"a","counts":{"b":300}});
+        assert!(s.encode_into(&invalid, &typ, &mut buff).is_err());
+        assert_eq!(buff, encoded);
+    }
+
+    #[test]
+    fn test_field_order_and_missing_fields() {
+        let s = Smith::new(
+            "struct Item{\n id: u8\n name: string\n note: Option<string>\n tags: Array<string>\n}",
+        );
+        let typ = s.get_type("Item").unwrap();
+        let ordered = s
+            .json2binary(r#"{"id":1,"name":"a","note":null,"tags":["x"]}"#, &typ)
+            .unwrap();
+        let reordered = s
+            .json2binary(r#"{"tags":["x"],"name":"a","id":1,"note":null}"#, &typ)
+            .unwrap();
+        assert_eq!(ordered, reordered);
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Item {
+            tags: Vec<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            note: Option<String>,
+            name: String,
+            id: u8,
+        }
+        let item = Item {
+            tags: vec!["x".to_owned()],
+            note: None,
+            name: "a".to_owned(),
+            id: 1,
+        };
+        let bin = s.rust2binary(&item, &typ).unwrap();
+        assert_eq!(bin, ordered);
+        assert_eq!(s.binary2rust::<Item>(&bin, &typ).unwrap(), item);
+
+        #[derive(Serialize)]
+        struct Named {
+            name: String,
+            note: Option<String>,
+        }
+        #[derive(Serialize)]
+        struct Flattened {
+            id: u8,
+            #[serde(flatten)]
+            named: Named,
+            tags: Vec<String>,
+        }
+        let flattened = Flattened {
+            id: 1,
+            named: Named {
+                name: "a".to_owned(),
+                note: None,
+            },
+            tags: vec!["x".to_owned()],
+        };
+        assert_eq!(s.rust2binary(&flattened, &typ).unwrap(), ordered);
+
+        //Missing fields
+        let json = r#"{"id":1,"note":"n"}"#;
+        assert!(s.json2binary(json, &typ).unwrap_err().contains("is missing field 'name'"));
+        assert!(s
+            .json2binary(r#"{"id":1,"name":"a","tags":[],"id":2}"#, &typ)
+            .unwrap_err()
+            .contains("field 'id' given more than once"));
+
+        let options = EncodeOptions::default();
+        let strict = s
+            .clone()
+            .with_encode_options(options.with_missing_fields(MissingFields::Error));
+        let json = r#"{"id":1,"name":"a","tags":[]}"#;
+        assert!(s.json2binary(json, &typ).is_ok());
+        assert!(strict.json2binary(json, &typ).is_err());
+
+        let defaults = s
+            .clone()
+            .with_encode_options(options.with_missing_fields(MissingFields::Default));
+        let bin = defaults.json2binary(r#"{"note":"n"}"#, &typ).unwrap();
+        let json = defaults.binary2json(&bin, &typ).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json, serde_json::json!({"id":0,"name":"","note":"n","tags":[]}));
+
+        //Enum payloads before the tag and missing payloads
+        let s = Smith::new("enum Shape{\n Circle(f32)\n Dot\n Label(Option<string>)\n}");
+        let typ = s.get_type("Shape").unwrap();
+        let ordered = s.json2binary(r#"{"tag":"Circle","val":1.5}"#, &typ).unwrap();
+        let reordered = s.json2binary(r#"{"val":1.5,"tag":"Circle"}"#, &typ).unwrap();
+        assert_eq!(ordered, reordered);
+        let err = s.json2binary(r#"{"val":"a","tag":"Circle"}"#, &typ).unwrap_err();
+        assert!(err.starts_with("Shape<Circle>"), "{err}");
+        assert!(s.json2binary(r#"{"val":1.5,"tag":"Dot"}"#, &typ).is_err());
+        let err = s.json2binary(r#"{"tag":"Circle"}"#, &typ).unwrap_err();
+        assert!(err.contains("is missing field 'val'"), "{err}");
+        assert!(s.json2binary(r#"{"val":1.5}"#, &typ).unwrap_err().contains("field 'tag'"));
+        assert_eq!(&*s.json2binary(r#"{"tag":"Label"}"#, &typ).unwrap(), &[5, 0]);
+        let defaults = s
+            .clone()
+            .with_encode_options(options.with_missing_fields(MissingFields::Default));
+        let bin = defaults.json2binary(r#"{"tag":"Circle"}"#, &typ).unwrap();
+        assert_eq!(&*bin, &[1, 0, 0, 0, 0]);
+
+        //Payloads before the tag keep bytes, NaN and integer map keys
+        use std::collections::BTreeMap;
+        let s = Smith::new(
+            "struct Blob{\n data: bytes\n x: f64\n ids: Map<u8,u8>\n}\n\
+             enum Msg{\n Blob(Blob)\n}",
+        );
+        let typ = s.get_type("Msg").unwrap();
+        #[derive(Serialize)]
+        struct Blob {
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            x: f64,
+            ids: BTreeMap<u8, u8>,
+        }
+        #[derive(Serialize)]
+        struct Tagged {
+            val: Blob,
+            tag: &'static str,
+        }
+        let blob = Blob {
+            data: vec![1, 2],
+            x: f64::NAN,
+            ids: BTreeMap::from([(3, 4)]),
+        };
+        let bin = s.rust2binary(&Tagged { val: blob, tag: "Blob" }, &typ).unwrap();
+        let mut expected = vec![1, 5, 1, 2];
+        expected.extend(f64::NAN.to_be_bytes());
+        expected.extend([3, 3, 4]);
+        assert_eq!(&*bin, &expected[..]);
+    }
+
+    #[test]
+    fn test_encode_modes() {
+        let strict = Smith::new("enum Shape{\n Dot(u8)\n Empty\n}\nstruct Item{\n id: u8\n}");
+        assert_eq!(strict.encode_options.mode, EncodeMode::Strict);
+        let s = strict
+            .clone()
+            .with_encode_options(EncodeOptions::default().with_mode(EncodeMode::Lenient));
+
+        //Same variant names, different order than the schema
+        #[derive(Serialize)]
+        enum Shape {
+            Empty,
+            Dot(u8),
+        }
+        #[derive(Serialize)]
+        enum Other {
+            Dot(u8),
+            Circle,
+        }
+        let typ = s.get_type("Shape").unwrap();
+        let dot = s.json2binary(r#"{"tag":"Dot","val":5}"#, &typ).unwrap();
+        assert_eq!(s.rust2binary(&Shape::Dot(5), &typ).unwrap(), dot);
+        assert_eq!(s.rust2binary(&Shape::Empty, &typ).unwrap().as_ref(), &[3]);
+        assert!(matches!(
+            strict.rust2binary(&Shape::Dot(5), &typ).as_ref().map_err(Error::root_cause),
+            Err(Error::EnumVariantWrongName { .. })
+        ));
+        //Unknown variants fall back to the serde index in lenient mode
+        assert_eq!(strict.rust2binary(&Other::Dot(5), &typ).unwrap(), dot);
+        let circle = s.rust2binary(&Other::Circle, &typ).unwrap();
+        assert_eq!(s.binary2json(&circle, &typ).unwrap(), r#"{"tag":"Empty"}"#);
+        assert!(strict.rust2binary(&Other::Circle, &typ).is_err());
+
+        let typ = s.get_type("Item").unwrap();
+        let json = r#"{"id":1,"other":2}"#;
+        assert_eq!(s.json2binary(json, &typ).unwrap().as_ref(), &[1]);
+        assert!(strict
+            .json2binary(json, &typ)
+            .unwrap_err()
+            .contains("struct 'Item' has no field 'other'"));
+    }
+
+    #[test]
+    fn test_parallel_arrays() {
+        use rayon::prelude::*;
+        use ser::AsyncSerializer;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Item {
+            id: u16,
+            name: String,
+        }
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Items {
+            #[serde(with = "crate::par")]
+            items: Vec<Item>,
+        }
+
+        let s = Smith::new(
+            "struct Item{\n id: u8\n name: string\n}\nstruct Items{\n items: Array<Item>\n}",
+        );
+        let typ = s.get_type("Items").unwrap();
+        let items = Items {
+            items: (0..10)
+                .map(|id| Item {
+                    id,
+                    name: id.to_string(),
+                })
+                .collect(),
+        };
+        let sequential = s.rust2binary(&items, &typ).unwrap();
+
+        for parallel in [
+            ParallelOptions::default().with_min_len(4).with_chunk_size(3),
+            ParallelOptions::default().with_min_len(0),
+            ParallelOptions::default().with_min_len(11),
+        ] {
+            let par = s.clone().with_parallel(parallel);
+            assert_eq!(par.rust2binary(&items, &typ).unwrap(), sequential);
+            assert_eq!(par.encoded_len(&items, &typ).unwrap(), sequential.len());
+            assert_eq!(par.binary2rust::<Items>(&sequential, &typ).unwrap(), items);
+        }
+        //Other serializers see a plain sequence
+        let json = serde_json::to_string(&items).unwrap();
+        assert_eq!(s.json2binary(&json, &typ).unwrap(), sequential);
+
+        //Element errors are returned from the workers instead of panicking
+        let mut invalid = items;
+        invalid.items[7].id = 300;
+        let par = s.clone().with_parallel(ParallelOptions::default().with_min_len(0));
+        let err = par.rust2binary(&invalid, &typ).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::TryFromIntError));
+        assert_eq!(err.path(), Some("Items.items[7].id"));
+
+        //Nested parallel arrays
+        #[derive(Serialize)]
+        struct Groups {
+            #[serde(with = "crate::par")]
+            groups: Vec<Items>,
+        }
+        let s = Smith::new(
+            "struct Item{\n id: u8\n name: string\n}\nstruct Items{\n items: Array<Item>\n}\n\
+             struct Groups{\n groups: Array<Items>\n}",
+        );
+        let typ = s.get_type("Groups").unwrap();
+        let groups = Groups {
+            groups: (0..3)
+                .map(|n| Items {
+                    items: (0..n).map(|id| Item { id, name: id.to_string() }).collect(),
+                })
+                .collect(),
+        };
+        let sequential = s.rust2binary(&groups, &typ).unwrap();
+        let par = s.clone().with_parallel(ParallelOptions::default().with_min_len(0));
+        assert_eq!(par.rust2binary(&groups, &typ).unwrap(), sequential);
+
+        //Different schemas encoded concurrently on the pool don't see each other's context
+        let other = Smith::new(
+            "struct Item{\n name: string\n id: u16\n}\nstruct Items{\n items: Array<Item>\n}\n\
+             struct Groups{\n groups: Array<Items>\n}",
+        );
+        let other_typ = other.get_type("Groups").unwrap();
+        let other_sequential = other.rust2binary(&groups, &other_typ).unwrap();
+        assert_ne!(other_sequential, sequential);
+        let other_par = other.with_parallel(ParallelOptions::default().with_min_len(0));
+        (0..64).into_par_iter().for_each(|i| {
+            if i % 2 == 0 {
+                assert_eq!(par.rust2binary(&groups, &typ).unwrap(), sequential);
+            } else {
+                let bin = other_par.rust2binary(&groups, &other_typ).unwrap();
+                assert_eq!(bin, other_sequential);
+            }
+        });
+
+        //Arrays shorter than the thread pool
+        let typ = SmithType::Array(Box::new(SmithType::U8));
+        let options = EncodeOptions::default().with_parallel(ParallelOptions::default());
+        let mut serializer = ser::Serializer::new(&s.prog, &typ).with_options(options);
+        (&mut serializer).serialize_slice_async(&[1u8]).unwrap();
+        assert_eq!(serializer.as_bytes(), &[3, 1]);
+        serializer.reset(&typ);
+        (&mut serializer).serialize_slice_async::<u8>(&[]).unwrap();
+        assert_eq!(serializer.as_bytes(), &[1]);
+    }
+
+    #[test]
+    fn test_parallel_decoding() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Item<'a> {
+            id: u16,
+            name: &'a str,
+        }
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Items<'a> {
+            #[serde(borrow, with = "crate::par")]
+            items: Vec<Item<'a>>,
+            last: u8,
+        }
+
+        let s = Smith::new(
+            "struct Item{\n id: u16\n name: string\n}\n\
+             struct Items{\n items: Array<Item>\n last: u8\n}",
+        );
+        let typ = s.get_type("Items").unwrap();
+        let names: Vec<String> = (0..100).map(|i| format!("item {i}")).collect();
+        let items = Items {
+            items: names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| Item { id: id as u16, name })
+                .collect(),
+            last: 7,
+        };
+        let plain = s.rust2binary(&items, &typ).unwrap();
+
+        let parallel = ParallelOptions::default().with_min_len(8).with_chunk_size(30);
+        let table = s
+            .clone()
+            .with_parallel(parallel.with_chunk_table(true))
+            .rust2binary(&items, &typ)
+            .unwrap();
+        //marker, length, chunk count and 4 chunk entries in place of the length
+        assert_eq!(&table[..8], &((par::CHUNK_TABLE_MARKER << 3).to_le_bytes()));
+        assert!(table.len() > plain.len());
+
+        //Sequential readers skip the table
+        assert_eq!(s.binary2rust::<Items>(&table, &typ).unwrap(), items);
+        assert_eq!(s.binary2json(&table, &typ), s.binary2json(&plain, &typ));
+
+        let par = s
+            .clone()
+            .with_decode_options(DecodeOptions::default().with_parallel(true));
+        assert_eq!(par.binary2rust::<Items>(&table, &typ).unwrap(), items);
+        assert_eq!(par.binary2rust::<Items>(&plain, &typ).unwrap(), items);
+
+        //Errors of a chunk keep their type
+        let mut invalid = table.to_vec();
+        let pos = invalid.iter().rposition(|b| *b == b'9').unwrap();
+        invalid[pos] = 0xff;
+        let err = par.binary2rust::<Items>(&invalid, &typ).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::UTF8Error(_)));
+        assert_eq!(err.path(), Some("Items.items[99].name"));
+
+        //Chunk tables with more bytes than the message are errors
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Ids {
+            #[serde(with = "crate::par")]
+            ids: Vec<u8>,
+        }
+        let ids = Smith::new("struct Ids{\n ids: Array<u8>\n}");
+        let ids_typ = ids.get_type("Ids").unwrap();
+        let mut bin = ids
+            .clone()
+            .with_parallel(ParallelOptions::default().with_min_len(0).with_chunk_table(true))
+            .rust2binary(&Ids { ids: vec![1, 2] }, &ids_typ)
+            .unwrap()
+            .into_vec();
+        //marker, length 2, 1 chunk of 2 elements in 2 bytes
+        assert_eq!(&bin[8..], &[5, 3, 5, 5, 1, 2]);
+        bin[11] = 7;
+        let ids = ids.with_decode_options(DecodeOptions::default().with_parallel(true));
+        let err = ids.binary2rust::<Ids>(&bin, &ids_typ).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::EndOfStream { needed: 1 }));
+
+        //Different schemas decoded concurrently on the pool
+        use rayon::prelude::*;
+        let other = Smith::new(
+            "struct Item{\n name: string\n id: u16\n}\n\
+             struct Items{\n items: Array<Item>\n last: u8\n}",
+        )
+        .with_parallel(parallel.with_chunk_table(true));
+        let other_typ = other.get_type("Items").unwrap();
+        let other_table = other.rust2binary(&items, &other_typ).unwrap();
+        assert_ne!(other_table, table);
+        let other = other.with_decode_options(DecodeOptions::default().with_parallel(true));
+        (0..64).into_par_iter().for_each(|i| {
+            let decoded = match i % 2 {
+                0 => par.binary2rust::<Items>(&table, &typ).unwrap(),
+                _ => other.binary2rust::<Items>(&other_table, &other_typ).unwrap(),
+            };
+            assert_eq!(decoded, items);
+        });
+    }
+
+    #[test]
+    fn test_partial_and_streaming_decode() {
+        let s = Smith::new(SCHEMA);
+        let typ = s.get_type("Packet").unwrap();
+        let packet = Types::get_rustvalue();
+        for len in 0..BIN.len() {
+            match s.decode_partial::<Types::Packet>(&BIN[..len], &typ).unwrap() {
+                de::Decoded::NeedMore(needed) => assert!(needed > 0 && needed <= BIN.len() - len),
+                decoded => panic!("{len} bytes decoded as {decoded:?}"),
+            }
+        }
+        let mut two = BIN.to_vec();
+        two.extend_from_slice(BIN);
+        assert_eq!(
+            s.decode_partial(&two, &typ).unwrap(),
+            de::Decoded::Value {
+                value: packet.clone(),
+                consumed: BIN.len()
+            }
+        );
+        assert_eq!(s.from_reader::<Types::Packet, _>(BIN, &typ).unwrap(), packet);
+        assert!(matches!(
+            s.from_reader::<Types::Packet, _>(&two[..], &typ),
+            Err(Error::TrailingCharacters)
+        ));
+        assert!(matches!(
+            s.from_reader::<Types::Packet, _>(&BIN[..5], &typ),
+            Err(Error::EndOfStream { .. })
+        ));
+
+        //Reader handing out one byte at a time
+        struct Trickle<'a>(&'a [u8]);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let Some((first, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buf[0] = *first;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+        let format = WireFormat::default()
+            .with_strings(StringEncoding::LengthPrefixed)
+            .with_endianness(Endianness::Little);
+        for s in [Smith::new(SCHEMA), Smith::new(SCHEMA).with_format(format)] {
+            let typ = s.get_type("Person").unwrap();
+            let people: Vec<Types::Person> = (0..3)
+                .map(|i| Types::Person {
+                    name: "x".repeat(i * 100),
+                    age: i as u8,
+                    desc: Types::Optional::None,
+                })
+                .collect();
+            let mut bin = Vec::new();
+            for person in &people {
+                s.encode_into(person, &typ, &mut bin).unwrap();
+            }
+            let mut decoder = de::StreamDecoder::new(&s, &typ, Trickle(&bin));
+            for person in &people {
+                assert_eq!(&decoder.next_message::<Types::Person>().unwrap().unwrap(), person);
+            }
+            assert!(decoder.next_message::<Types::Person>().unwrap().is_none());
+
+            //Streams ending inside a message
+            let mut decoder = de::StreamDecoder::new(&s, &typ, Trickle(&bin[..bin.len() - 3]));
+            decoder.next_message::<Types::Person>().unwrap().unwrap();
+            decoder.next_message::<Types::Person>().unwrap().unwrap();
+            assert!(matches!(
+                decoder.next_message::<Types::Person>(),
+                Err(Error::EndOfStream { needed }) if needed > 0
+            ));
+        }
+
+        //Invalid messages and failing readers are errors
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Flag {
+            on: bool,
+            n: u16,
+        }
+        let s = Smith::new("struct Flag{\n on: bool\n n: u16\n}");
+        let typ = s.get_type("Flag").unwrap();
+        let mut decoder = de::StreamDecoder::new(&s, &typ, Trickle(&[1, 0, 5, 2, 0, 5]));
+        let flag = decoder.next_message::<Flag>().unwrap();
+        assert_eq!(flag, Some(Flag { on: true, n: 5 }));
+        let err = decoder.next_message::<Flag>().unwrap_err();
+        assert!(matches!(err.root_cause(), Error::InvalidBool(2)));
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::ConnectionReset.into())
+            }
+        }
+        let mut decoder = de::StreamDecoder::new(&s, &typ, Broken);
+        assert!(matches!(decoder.next_message::<Flag>(), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let schema = "enum Kind{\n A\n B(u8)\n}\n\
+                      struct Tree{\n children: Array<Tree>\n}\n\
+                      struct Doc{\n name: string\n kind: Kind\n vals: Array<u32>\n}";
+        let s = Smith::new(schema);
+        let kind = s.get_type("Kind").unwrap();
+        let tree = s.get_type("Tree").unwrap();
+        let doc = s.get_type("Doc").unwrap();
+
+        //Variant 2 does not exist, variant 1 is missing on the rust side
+        #[derive(Deserialize, Debug)]
+        enum Short {
+            A,
+        }
+        assert!(matches!(
+            s.binary2rust::<serde_json::Value>(&[5], &kind).as_ref().map_err(Error::root_cause),
+            Err(Error::EnumVariantOutOfRange { index: 2, .. })
+        ));
+        assert!(matches!(
+            s.binary2rust::<Short>(&[3, 0], &kind),
+            Err(Error::EnumVariantWrongName { .. })
+        ));
+        assert!(s.binary2rust::<Short>(&[1], &kind).is_ok());
+        assert!(s.binary2json(&[5], &kind).is_err());
+
+        //Ten trees nested in each other
+        let mut nested = vec![3; 9];
+        nested.push(1);
+        assert!(s.binary2rust::<serde_json::Value>(&nested, &tree).is_ok());
+        let shallow = s.clone().with_decode_options(
+            DecodeOptions::default().with_limits(DecodeLimits::default().with_max_depth(4)),
+        );
+        let err = shallow.binary2rust::<serde_json::Value>(&nested, &tree).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::LimitExceeded { max: 4, .. }));
+        assert_eq!(
+            err.to_string(),
+            "Tree.children[0].children[0] (byte 2): nesting depth exceeds the limit of 4"
+        );
+
+        let bin = s
+            .json2binary(r#"{"name":"abc","kind":{"tag":"A"},"vals":[1,2,3]}"#, &doc)
+            .unwrap();
+        assert!(s.binary2json(&bin, &doc).is_ok());
+        let limited = |limits: DecodeLimits| {
+            s.clone()
+                .with_decode_options(DecodeOptions::default().with_limits(limits))
+                .binary2json(&bin, &doc)
+                .unwrap_err()
+        };
+        assert!(limited(DecodeLimits::default().with_max_array_elements(2))
+            .contains("array elements exceeds the limit of 2"));
+        assert!(limited(DecodeLimits::default().with_max_string_len(2))
+            .contains("string length exceeds the limit of 2"));
+        //The length is rejected before any element is read - 3 * 4 bytes are not there
+        assert!(s
+            .binary2json(&bin[..8], &doc)
+            .unwrap_err()
+            .contains("10 more byte(s) needed"));
+    }
+    #[test]
+    fn test_error_context() {
+        let schema = "struct Item{\n amount: u16\n}\n\
+                      struct Order{\n items: Array<Item>\n}\n\
+                      enum Payload{\n Ping\n Order(Order)\n}\n\
+                      struct Packet{\n id: u8\n payload: Payload\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Packet").unwrap();
+        let json = concat!(
+            r#"{"id":1,"payload":{"tag":"Order","val":"#,
+            r#"{"items":[{"amount":1},{"amount":2}]}}}"#
+        );
+        let bin = s.json2binary(json, &typ).unwrap();
+        assert_eq!(&*bin, &[1, 3, 5, 0, 1, 0, 2]);
+
+        assert_eq!(
+            s.binary2json(&bin[..6], &typ).unwrap_err(),
+            "Packet.payload<Order>.items[1].amount (byte 5): \
+             data ends inside the message, 1 more byte(s) needed"
+        );
+        let err = s.json2binary(&json.replace('2', "70000"), &typ).unwrap_err();
+        assert!(err.starts_with("Packet.payload<Order>.items[1].amount (byte 5): "));
+        //Json is encoded as SmithValue, the error keeps its type
+        let value: SmithValue = json5::from_str(&json.replace('2', "70000")).unwrap();
+        let typed = s.encode_value(&value, &typ).unwrap_err();
+        assert_eq!(typed.to_string(), err);
+        assert_eq!(typed.path(), Some("Packet.payload<Order>.items[1].amount"));
+        assert!(!matches!(typed.root_cause(), Error::SerdeMessage(_)));
+
+        #[derive(Serialize)]
+        struct Packet {
+            id: u8,
+            payload: &'static str,
+        }
+        let err = s.rust2binary(&Packet { id: 1, payload: "x" }, &typ).unwrap_err();
+        assert_eq!(err.path(), Some("Packet.payload"));
+        assert_eq!(err.offset(), Some(1));
+        assert!(matches!(err.root_cause(), Error::MissmatchedType { .. }));
+    }
+    #[test]
+    fn test_smith_value() {
+        let schema = "flags Perms { Read Write }\n\
+                      struct Item{\n amount: u16\n}\n\
+                      enum Payload{\n Ping\n Items(Array<Item>)\n}\n\
+                      struct Packet{\n id: udInt\n name: string\n payload: Payload\n\
+                       perms: Perms\n tags: Map<string,i8>\n note: Option<string>\n\
+                       at: timestamp\n price: decimal\n data: bytes\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Packet").unwrap();
+        let json = concat!(
+            r#"{"id":300,"name":"box","payload":{"tag":"Items","val":[{"amount":7}]},"#,
+            r#""perms":["Write"],"tags":{"a":-1},"note":null,"#,
+            r#""at":"2023-04-01T12:30:00Z","price":"-1.50","data":"AQI="}"#
+        );
+        let bin = s.json2binary(json, &typ).unwrap();
+
+        let mut value = s.decode_value(&bin, &typ).unwrap();
+        assert_eq!(value["id"], SmithValue::UInt(300));
+        assert_eq!(value["name"].as_str(), Some("box"));
+        let (variant, items) = value["payload"].as_variant().unwrap();
+        assert_eq!(variant, "Items");
+        assert_eq!(items.unwrap()[0]["amount"], SmithValue::U16(7));
+        assert_eq!(value["perms"], SmithValue::Flags(vec!["Write".into()]));
+        assert_eq!(
+            value["tags"],
+            SmithValue::Map(vec![(SmithValue::String("a".into()), SmithValue::I8(-1))])
+        );
+        assert_eq!(value["note"], SmithValue::Option(None));
+        assert_eq!(value["missing"], SmithValue::Option(None));
+        assert_eq!(
+            value["price"],
+            SmithValue::Decimal {
+                mantissa: -150,
+                scale: 2
+            }
+        );
+        assert!(matches!(value["at"], SmithValue::Timestamp { nanos: 0, .. }));
+        assert_eq!(value["data"].as_bytes(), Some(&[1, 2][..]));
+
+        assert_eq!(&*s.encode_value(&value, &typ).unwrap(), &*bin);
+        assert_eq!(
+            serde_json::to_value(&value).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&s.binary2json(&bin, &typ).unwrap()).unwrap()
+        );
+
+        value["name"] = SmithValue::String("crate".into());
+        let untyped: SmithValue = serde_json::from_str(&serde_json::to_string(&value).unwrap())
+            .unwrap();
+        assert_eq!(untyped["id"], SmithValue::UInt(300));
+        let bin = s.encode_value(&untyped, &typ).unwrap();
+        assert_eq!(s.decode_value(&bin, &typ).unwrap(), value);
+    }
+
+    #[test]
+    fn test_extract() {
+        let schema = "flags Perms { Read Write }\n\
+                      struct Item{\n amount: u16\n note: Option<string>\n}\n\
+                      struct Order{\n table_number: u8\n items: Array<Item>\n}\n\
+                      enum Payload{\n Ping\n Order(Order)\n}\n\
+                      struct Packet{\n id: udInt\n name: string\n tags: Map<string,i8>\n\
+                       perms: Perms\n at: timestamp\n fixed: [u16;2]\n payload: Payload\n\
+                       last: dInt\n}";
+        let json = concat!(
+            r#"{"id":300,"name":"box","tags":{"a":-1,"b":2},"perms":["Write"],"#,
+            r#""at":"2023-04-01T12:30:00Z","fixed":[1,2],"payload":{"tag":"Order","val":"#,
+            r#"{"table_number":12,"items":[{"amount":7,"note":"hot"},{"amount":8,"note":null}]}},"#,
+            r#""last":-5}"#
+        );
+        for s in [
+            Smith::new(schema),
+            Smith::new(schema)
+                .with_format(WireFormat::default().with_strings(StringEncoding::LengthPrefixed)),
+        ] {
+            let typ = s.get_type("Packet").unwrap();
+            let bin = s.json2binary(json, &typ).unwrap();
+            let extract = |path| s.extract(&bin, &typ, path).unwrap();
+
+            assert_eq!(extract("id"), Some(SmithValue::UInt(300)));
+            assert_eq!(extract("last"), Some(SmithValue::Int(-5)));
+            assert_eq!(extract("payload.val.table_number"), Some(SmithValue::U8(12)));
+            assert_eq!(extract("payload.Order.items.1.amount"), Some(SmithValue::U16(8)));
+            let hot = SmithValue::Option(Some(SmithValue::String("hot".into()).into()));
+            assert_eq!(extract("payload.val.items.0.note"), Some(hot));
+            assert_eq!(extract("payload.val.items.1.note"), Some(SmithValue::Option(None)));
+            assert_eq!(extract("payload.val.items.1.note.x"), None);
+            assert_eq!(extract("payload.val.items.2"), None);
+            assert_eq!(extract("tags.b"), Some(SmithValue::I8(2)));
+            assert_eq!(extract("tags.c"), None);
+            assert_eq!(extract("fixed.1"), Some(SmithValue::U16(2)));
+            assert_eq!(extract("at.nanos"), Some(SmithValue::UInt(0)));
+            assert_eq!(extract(""), Some(s.decode_value(&bin, &typ).unwrap()));
+            assert_eq!(s.extract_as::<u64>(&bin, &typ, "id").unwrap(), Some(300));
+            assert_eq!(s.extract_as::<&str>(&bin, &typ, "name").unwrap(), Some("box"));
+
+            let err = s.extract(&bin, &typ, "payload.val.missing").unwrap_err();
+            assert!(matches!(err.root_cause(), Error::ValueNotHavingField(f) if f == "missing"));
+            assert!(s.extract(&bin, &typ, "id.x").is_err());
+            assert!(s.extract(&bin[..bin.len() - 1], &typ, "last").is_err());
+        }
+
+        let s = Smith::new(schema);
+        let typ = s.get_type("Packet").unwrap();
+        let ping = json.replace(
+            &json[json.find(r#""payload""#).unwrap()..json.find(r#","last""#).unwrap()],
+            r#""payload":{"tag":"Ping"}"#,
+        );
+        let bin = s.json2binary(&ping, &typ).unwrap();
+        assert_eq!(s.extract(&bin, &typ, "payload.val.table_number").unwrap(), None);
+        assert_eq!(s.extract(&bin, &typ, "last").unwrap(), Some(SmithValue::Int(-5)));
+
+        //Arrays with chunk table are entered at the chunk of the element
+        #[derive(Serialize)]
+        struct Item {
+            id: u8,
+            name: String,
+        }
+        #[derive(Serialize)]
+        struct Items {
+            #[serde(with = "crate::par")]
+            items: Vec<Item>,
+            last: u8,
+        }
+        let parallel = ParallelOptions::default().with_min_len(1).with_chunk_size(3);
+        let s = Smith::new("struct Item{\n id: u8\n name: string\n}\n\
+                            struct Items{\n items: Array<Item>\n last: u8\n}")
+            .with_parallel(parallel.with_chunk_table(true));
+        let typ = s.get_type("Items").unwrap();
+        let items = Items {
+            items: (0..10).map(|id| Item { id, name: id.to_string() }).collect(),
+            last: 42,
+        };
+        let bin = s.rust2binary(&items, &typ).unwrap();
+        assert_eq!(s.extract_as::<u8>(&bin, &typ, "items.7.id").unwrap(), Some(7));
+        assert_eq!(s.extract_as::<String>(&bin, &typ, "items.9.name").unwrap(), Some("9".into()));
+        assert_eq!(s.extract_as::<u8>(&bin, &typ, "items.10.id").unwrap(), None);
+        assert_eq!(s.extract_as::<u8>(&bin, &typ, "last").unwrap(), Some(42));
+    }
+
+    #[test]
+    fn test_events() {
+        use de::{Event, Scalar};
+
+        let schema = "flags Perms { Read Write }\n\
+                      struct Item{\n amount: u16\n note: Option<string>\n}\n\
+                      enum Payload{\n Ping\n Items(Array<Item>)\n}\n\
+                      struct Packet{\n id: udInt\n tags: Map<string,i8>\n perms: Perms\n\
+                       payload: Payload\n last: Payload\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Packet").unwrap();
+        let json = concat!(
+            r#"{"id":300,"tags":{"a":-1},"perms":["Write"],"payload":{"tag":"Items","val":"#,
+            r#"[{"amount":7,"note":"hot"},{"amount":8,"note":null}]},"last":{"tag":"Ping"}}"#
+        );
+        let bin = s.json2binary(json, &typ).unwrap();
+        let events: Vec<_> = s.events(&bin, &typ).unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            events,
+            [
+                Event::StructStart("Packet"),
+                Event::Field("id"),
+                Event::Scalar(Scalar::UInt(300)),
+                Event::Field("tags"),
+                Event::MapStart(1),
+                Event::Scalar(Scalar::Str("a")),
+                Event::Scalar(Scalar::I8(-1)),
+                Event::End,
+                Event::Field("perms"),
+                Event::Scalar(Scalar::Flags(0b10)),
+                Event::Field("payload"),
+                Event::EnumVariant("Items"),
+                Event::ArrayStart(2),
+                Event::StructStart("Item"),
+                Event::Field("amount"),
+                Event::Scalar(Scalar::U16(7)),
+                Event::Field("note"),
+                Event::Scalar(Scalar::Str("hot")),
+                Event::End,
+                Event::StructStart("Item"),
+                Event::Field("amount"),
+                Event::Scalar(Scalar::U16(8)),
+                Event::Field("note"),
+                Event::None,
+                Event::End,
+                Event::End,
+                Event::Field("last"),
+                Event::EnumVariant("Ping"),
+                Event::End,
+            ]
+        );
+
+        //Iteration ends with the first error
+        let mut events = s.events(&bin[..bin.len() - 2], &typ).unwrap();
+        let err = events.find_map(Result::err).unwrap();
+        assert_eq!(err.offset(), Some(bin.len() - 2));
+        assert!(matches!(err.root_cause(), Error::EndOfStream { .. }));
+        assert!(events.next().is_none());
+
+        let mut trailing = bin.to_vec();
+        trailing.push(0);
+        let err = s.events(&trailing, &typ).unwrap().last().unwrap().unwrap_err();
+        assert!(matches!(err.root_cause(), Error::TrailingCharacters));
+        //Corrupt values end the iteration with their offset
+        let mut corrupt = bin.to_vec();
+        //Perms has 2 flags, udInt 300 and the map take 5 bytes
+        corrupt[6] = 0b100;
+        let err = s.events(&corrupt, &typ).unwrap().find_map(Result::err).unwrap();
+        assert_eq!(err.offset(), Some(6));
+        assert!(matches!(err.root_cause(), Error::UnknownFlag(_)));
+        corrupt[6] = 0b10;
+        //Payload variant 2 does not exist
+        corrupt[7] = 5;
+        let err = s.events(&corrupt, &typ).unwrap().find_map(Result::err).unwrap();
+        assert!(matches!(err.root_cause(), Error::EnumVariantOutOfRange { index: 2, .. }));
+
+        //Nesting deeper than the limit
+        let limits = DecodeLimits::default().with_max_depth(2);
+        let limited = s
+            .clone()
+            .with_decode_options(DecodeOptions::default().with_limits(limits));
+        let err = limited.events(&bin, &typ).unwrap().find_map(Result::err).unwrap();
+        assert!(matches!(err.root_cause(), Error::LimitExceeded { limit: "nesting depth", .. }));
+
+        //Messages of another wire format are rejected before the first event
+        let little = s.with_format(WireFormat::default().with_endianness(Endianness::Little));
+        let bin = little.json2binary(json, &typ).unwrap();
+        assert!(matches!(
+            Smith::new(schema).events(&bin, &typ).err(),
+            Some(Error::WireFormatMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        #[derive(Serialize)]
+        struct Item {
+            id: u8,
+            name: String,
+        }
+        #[derive(Serialize)]
+        struct Items {
+            #[serde(with = "crate::par")]
+            items: Vec<Item>,
+            ids: Vec<u32>,
+            kind: Kind,
+        }
+        #[derive(Serialize)]
+        enum Kind {
+            A,
+        }
+        let schema = "struct Item{\n id: u8\n name: string\n}\n\
+                      enum Kind{\n A\n B(u8)\n}\n\
+                      struct Items{\n items: Array<Item>\n ids: Array<u32>\n kind: Kind\n}";
+        let items = Items {
+            items: (0..5).map(|id| Item { id, name: id.to_string() }).collect(),
+            ids: vec![1, 2, 3],
+            kind: Kind::A,
+        };
+        let parallel = ParallelOptions::default().with_min_len(1).with_chunk_size(2);
+        for s in [
+            Smith::new(schema),
+            Smith::new(schema).with_parallel(parallel.with_chunk_table(true)),
+        ] {
+            let typ = s.get_type("Items").unwrap();
+            let mut bin = s.rust2binary(&items, &typ).unwrap().into_vec();
+            let report = s.validate(&bin, &typ).unwrap();
+            assert_eq!(report, de::ValidationReport { len: bin.len(), elements: 8 });
+
+            //Invalid UTF-8 in the name of items[3]
+            let name = bin.iter().rposition(|b| *b == b'3').unwrap();
+            bin[name] = 0xff;
+            let err = s.validate(&bin, &typ).unwrap_err();
+            assert_eq!(err.path(), Some("Items.items[3].name"));
+            assert!(matches!(err.root_cause(), Error::UTF8Error(_)));
+            //Skipping does not look into strings
+            assert_eq!(s.extract(&bin, &typ, "ids.2").unwrap(), Some(SmithValue::U32(3)));
+            bin[name] = b'3';
+
+            let last = bin.len() - 1;
+            //udInt 2
+            bin[last] = 5;
+            let err = s.validate(&bin, &typ).unwrap_err();
+            assert!(matches!(err.root_cause(), Error::EnumVariantOutOfRange { index: 2, .. }));
+            bin[last] = 1;
+
+            bin.push(0);
+            let err = s.validate(&bin, &typ).unwrap_err();
+            assert_eq!(err.offset(), Some(last + 1));
+            assert!(matches!(err.root_cause(), Error::TrailingCharacters));
+            assert!(s.validate(&bin[..last - 4], &typ).is_err());
+        }
+
+        //Bools and presence bytes other than 0 / 1 are invalid for every reader
+        let s = Smith::new("struct Flag{\n on: bool\n note: Option<u8>\n}");
+        let typ = s.get_type("Flag").unwrap();
+        assert_eq!(s.validate(&[1, 1, 7], &typ).unwrap().len, 3);
+        for (bin, path) in [([2, 0], "Flag.on"), ([1, 2], "Flag.note")] {
+            let err = s.validate(&bin, &typ).unwrap_err();
+            assert_eq!(err.path(), Some(path));
+            assert!(matches!(err.root_cause(), Error::InvalidBool(2)));
+            let err = s.binary2rust::<serde_json::Value>(&bin, &typ).unwrap_err();
+            assert!(matches!(err.root_cause(), Error::InvalidBool(2)));
+            let err = s.events(&bin, &typ).unwrap().find_map(Result::err).unwrap();
+            assert!(matches!(err.root_cause(), Error::InvalidBool(2)));
+            let dump = s.dump(&bin, &typ);
+            assert!(matches!(dump.error.unwrap().error, Error::InvalidBool(2)));
+        }
+        assert!(s.extract(&[0, 2], &typ, "note").is_err());
+    }
+
+    #[test]
+    fn test_dump() {
+        let schema = "flags Perms { Read Write }\n\
+                      struct Item{\n amount: u16\n note: Option<string>\n}\n\
+                      enum Payload{\n Ping\n Items(Array<Item>)\n}\n\
+                      struct Packet{\n id: udInt\n tags: Map<string,i8>\n perms: Perms\n\
+                       payload: Payload\n}";
+        let s = Smith::new(schema);
+        let typ = s.get_type("Packet").unwrap();
+        let json = concat!(
+            r#"{"id":300,"tags":{"a":-1},"perms":["Write"],"payload":{"tag":"Items","val":"#,
+            r#"[{"amount":7,"note":"hot"},{"amount":8,"note":null}]}}"#
+        );
+        let bin = s.json2binary(json, &typ).unwrap();
+        let dump = s.dump(&bin, &typ);
+        assert!(dump.error.is_none());
+        assert_eq!(dump.lines.len(), 15);
+        let line = |path: &str, typ: &str, value: &str, range| de::DumpLine {
+            range,
+            path: path.to_owned(),
+            typ: typ.to_owned(),
+            value: value.to_owned(),
+        };
+        assert_eq!(dump.lines[1], line("Packet.id", "udInt (tag 10)", "300", 0..2));
+        assert_eq!(dump.lines[4], line("Packet.tags[0]", "i8", "-1", 5..6));
+        assert_eq!(dump.lines[5], line("Packet.perms", "Perms", "Write", 6..7));
+        assert_eq!(dump.lines[6], line("Packet.payload", "variant (tag 1)", "Items", 7..8));
+        let note = "Packet.payload<Items>[0].note";
+        assert_eq!(dump.lines[10], line(note, "Option<string>", "Some", 11..12));
+        assert_eq!(dump.lines[11], line(note, "string", "\"hot\"", 12..16));
+        let text = dump.to_string();
+        assert!(text.contains("   12..16    68 6f 74 00 "));
+
+        //Corrupt messages are dumped up to the error
+        let dump = s.dump(&bin[..bin.len() - 2], &typ);
+        assert_eq!(dump.lines.len(), 13);
+        let error = dump.error.as_ref().unwrap();
+        assert_eq!(error.path, "Packet.payload<Items>[1].amount");
+        assert_eq!((error.offset, dump.data.len()), (16, 17));
+        assert!(matches!(error.error, Error::EndOfStream { needed: 1 }));
+        let text = dump.to_string();
+        assert!(text.ends_with("error: data ends inside the message, 1 more byte(s) needed\n\
+                                1 byte(s) not decoded\n"));
+
+        let mut trailing = bin.to_vec();
+        trailing.push(7);
+        let dump = s.dump(&trailing, &typ);
+        assert_eq!(dump.lines.len(), 15);
+        assert!(matches!(dump.error.unwrap().error, Error::TrailingCharacters));
+
+        //Variant 2 of the payload does not exist
+        let mut corrupt = bin.to_vec();
+        corrupt[7] = 5;
+        let dump = s.dump(&corrupt, &typ);
+        let error = dump.error.as_ref().unwrap();
+        assert_eq!((error.path.as_str(), error.offset), ("Packet.payload", 7));
+        assert!(matches!(error.error, Error::EnumVariantOutOfRange { index: 2, .. }));
+        assert!(dump.to_string().contains("error: "));
+
+        //Nothing is dumped for messages of another wire format
+        let little = WireFormat::default().with_endianness(Endianness::Little);
+        let little = s.clone().with_format(little);
+        let bin = little.json2binary(json, &typ).unwrap();
+        let dump = s.dump(&bin, &typ);
+        assert!(dump.lines.is_empty());
+        assert!(matches!(dump.error.unwrap().error, Error::WireFormatMismatch { .. }));
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let s = Smith::new(SCHEMA);
+        let typ = s.get_type("Packet").unwrap();
+        let packet: Types::Packet = s.binary2rust(BIN, &typ).unwrap();
+        assert_eq!(s.encoded_len(&packet, &typ).unwrap(), BIN.len());
+
+        let s = s.with_format(WireFormat::default().with_endianness(Endianness::Little));
+        let bin = s.rust2binary(&packet, &typ).unwrap();
+        assert_eq!(s.encoded_len(&packet, &typ).unwrap(), bin.len());
+
+        let s = Smith::new(
+            "struct Point{\n x: i32\n y: i32\n}\n\
+             struct Sample{\n at: Point\n id: uuid\n n: udInt\n p: [u16;3]\n}\n\
+             enum Shape{\n Empty\n Dot(Point)\n}\n\
+             struct Node{\n v: u8\n next: Option<Node>\n}",
+        );
+        let point = s.get_type("Point").unwrap();
+        let point_typ = s.get_types().iter().find(|t| t.get_name() == "Point").unwrap();
+        assert!(point_typ.is_fixed_size(s.get_types()));
+        assert_eq!(point_typ.max_size(s.get_types()), Some(8));
+        assert_eq!(s.size_bounds(&point), size::SizeBounds::exact(8));
+
+        let sample = s.get_type("Sample").unwrap();
+        let bounds = s.size_bounds(&sample);
+        assert_eq!((bounds.min, bounds.max), (8 + 16 + 1 + 6, Some(8 + 16 + 8 + 6)));
+        assert!(!bounds.is_fixed());
+
+        let shape = s.get_type("Shape").unwrap();
+        assert_eq!(s.size_bounds(&shape), size::SizeBounds { min: 1, max: Some(9) });
+
+        let node = s.get_type("Node").unwrap();
+        assert_eq!(s.size_bounds(&node), size::SizeBounds { min: 2, max: None });
+
+        //Values that can't be encoded have no length
+        let err = s.encoded_len(&serde_json::json!({"x": 1}), &point).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::MissingField { .. }), "{err}");
+        let err = s.encoded_len(&serde_json::json!({"x": 1, "y": 1u64 << 40}), &point);
+        assert_eq!(err.unwrap_err().path(), Some("Point.y"));
+        let err = s.encoded_len(&serde_json::json!({"tag": "Circle"}), &shape).unwrap_err();
+        assert!(matches!(err.root_cause(), Error::EnumVariantNotFound(_)));
+        //Fixed-size types starting with a header byte take one more
+        let value = serde_json::json!({"x": 0xa000_0000u32 as i32, "y": 0});
+        assert_eq!(s.encoded_len(&value, &point).unwrap(), 9);
+    }
+
 
 
 
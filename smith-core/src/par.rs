@@ -0,0 +1,241 @@
+//! Parallel encoding of large arrays on the rayon thread pool.
+//!
+//! serde hands sequence elements to the serializer one at a time, so the value has to ask for
+//! parallel encoding: wrap slices in [ParSlice] or mark `Vec` fields with
+//! `#[serde(with = "smith_core::par")]`.
+//! Elements are only encoded in parallel by a smith serializer with [crate::ParallelOptions]
+//! enabled and a shared program ([crate::Smith] provides both, see
+//! [crate::ser::Serializer::with_shared_program]), and for arrays of at least `min_len`
+//! elements. Other serializers see a plain sequence.
+//!
+//! serde's generic API can't pass the schema to [ParSlice]: the smith serializer installs the
+//! context in a thread-local of the calling thread for the duration of `serialize`, and
+//! [ParSlice] takes it before any work reaches the pool. Workers get owned copies of program,
+//! type and options - nested arrays and concurrent encodes of other schemas install their own.
+//! Decoding hands the chunk table over the same way.
+//!
+//! With [crate::ParallelOptions::chunk_table] the chunk sizes are written in front of the
+//! elements, [crate::DecodeOptions::parallel] decodes such arrays in parallel as well.
+//! Readers without it (and json) skip the table and read the elements in order.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Root {
+//!     #[serde(with = "smith_core::par")]
+//!     person: Vec<Person>,
+//! }
+//! let smith = Smith::new(SCHEMA).with_parallel(ParallelOptions::default());
+//! ```
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use serde::de::{self, SeqAccess, Unexpected, Visitor};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::format::{DecodeOptions, EncodeOptions, ParallelOptions, WireFormat};
+use crate::resolver::ResolvedSmithProgram;
+use crate::smith_serde::{Error, Result};
+use crate::SmithType;
+
+/// Newtype / unit struct name used to hand the encoded array to the smith serializer
+pub(crate) const TOKEN: &str = "$smith_core::par";
+
+/// udInt written in place of the array length in front of a chunk table -
+/// the largest udInt, no array can be that long
+pub const CHUNK_TABLE_MARKER: u64 = (1 << 61) - 1;
+
+/// Everything needed to encode the elements, provided by the serializer. Owned, so the
+/// workers don't borrow from the serializer
+pub(crate) struct ParContext {
+    pub(crate) prog: Arc<ResolvedSmithProgram>,
+    pub(crate) elemtyp: SmithType<usize>,
+    pub(crate) format: WireFormat,
+    pub(crate) options: EncodeOptions,
+    pub(crate) parallel: ParallelOptions,
+    //Encoded array (length prefix included) or the error, read by the serializer afterwards
+    pub(crate) output: Rc<RefCell<Option<Result<Vec<u8>>>>>,
+}
+
+thread_local! {
+    //Set while the serializer serializes the inner value of a ParSlice, taken by it
+    static CONTEXT: RefCell<Option<ParContext>> = const { RefCell::new(None) };
+}
+
+/// Provides `context` to the [ParSlice] serialized until the guard is dropped
+pub(crate) fn enter(context: ParContext) -> ContextGuard {
+    ContextGuard(CONTEXT.replace(Some(context)))
+}
+
+pub(crate) struct ContextGuard(Option<ParContext>);
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT.set(self.0.take());
+    }
+}
+
+/// Chunk table of an array, provided by the deserializer - the chunks follow as borrowed bytes
+pub(crate) struct DecodeContext {
+    pub(crate) prog: Arc<ResolvedSmithProgram>,
+    pub(crate) elemtyp: SmithType<usize>,
+    pub(crate) format: WireFormat,
+    pub(crate) options: DecodeOptions,
+    //Position of the first chunk in the message
+    pub(crate) offset: usize,
+    //(elements, bytes) of every chunk
+    pub(crate) chunks: Vec<(usize, usize)>,
+    //Error of the chunk decoding, serde only lets the visitor return it as message
+    pub(crate) error: Rc<RefCell<Option<Error>>>,
+}
+
+thread_local! {
+    //Set while the deserializer hands a chunked array to `ParVisitor`, taken by it
+    static DECODE_CONTEXT: RefCell<Option<DecodeContext>> = const { RefCell::new(None) };
+}
+
+/// Provides `context` to the [deserialize] visitor until the guard is dropped
+pub(crate) fn enter_decode(context: DecodeContext) -> DecodeGuard {
+    DecodeGuard(DECODE_CONTEXT.replace(Some(context)))
+}
+
+pub(crate) struct DecodeGuard(Option<DecodeContext>);
+
+impl Drop for DecodeGuard {
+    fn drop(&mut self) {
+        DECODE_CONTEXT.set(self.0.take());
+    }
+}
+
+/// Slice encoded in parallel by smith serializers, a plain sequence for all others
+pub struct ParSlice<'a, T>(pub &'a [T]);
+
+impl<T: Serialize + Sync> Serialize for ParSlice<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(TOKEN, &ParSeq(self.0))
+    }
+}
+
+struct ParSeq<'a, T>(&'a [T]);
+
+impl<T: Serialize + Sync> Serialize for ParSeq<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match CONTEXT.take() {
+            Some(ctx) if self.0.len() >= ctx.parallel.min_len => {
+                let mut inner = crate::ser::Serializer::new(&ctx.prog, &ctx.elemtyp)
+                    .with_shared_program(&ctx.prog)
+                    .with_format(ctx.format)
+                    .with_options(ctx.options);
+                let output = inner
+                    .write_par_slice(&ctx.elemtyp, self.0, Some(ctx.parallel))
+                    .map(|_| inner.buffer());
+                let failed = output.as_ref().err().map(ToString::to_string);
+                *ctx.output.borrow_mut() = Some(output);
+                match failed {
+                    //The serializer returns the original error
+                    Some(msg) => Err(ser::Error::custom(msg)),
+                    None => serializer.serialize_unit_struct(TOKEN),
+                }
+            }
+            _ => serializer.collect_seq(self.0),
+        }
+    }
+}
+
+/// `#[serde(with = "smith_core::par")]` / `serialize_with` for `Vec<T>` fields
+pub fn serialize<T, S>(value: &[T], serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    T: Serialize + Sync,
+    S: Serializer,
+{
+    ParSlice(value).serialize(serializer)
+}
+
+/// `#[serde(with = "smith_core::par")]` / `deserialize_with` for `Vec<T>` fields.
+/// Arrays with chunk table are decoded in parallel if enabled, see [DecodeOptions::parallel]
+pub fn deserialize<'de, T, D>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    T: Deserialize<'de> + Send,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(TOKEN, ParVisitor(PhantomData))
+}
+
+struct ParVisitor<T>(PhantomData<T>);
+
+/// Bytes, offset and element indices of a chunk
+type Chunk<'de> = (&'de [u8], usize, Range<usize>);
+
+impl<'de, T: Deserialize<'de> + Send> Visitor<'de> for ParVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Vec<T>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut out = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(elem) = seq.next_element()? {
+            out.push(elem);
+        }
+        Ok(out)
+    }
+
+    /// Chunks of an array handed over by the smith deserializer
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> std::result::Result<Vec<T>, E> {
+        let Some(ctx) = DECODE_CONTEXT.take() else {
+            return Err(E::invalid_type(Unexpected::Bytes(v), &self));
+        };
+        let (mut rest, mut offset, mut index) = (v, ctx.offset, 0);
+        let chunks: Option<Vec<Chunk<'de>>> = ctx
+            .chunks
+            .iter()
+            .map(|&(elements, len)| {
+                let (bytes, next) = rest.split_at_checked(len)?;
+                let chunk = (bytes, offset, index..index + elements);
+                (rest, offset, index) = (next, offset + len, index + elements);
+                Some(chunk)
+            })
+            .collect();
+        let Some(chunks) = chunks else {
+            return Err(E::custom("chunk table exceeds the array bytes"));
+        };
+
+        let (prog, elemtyp) = (&ctx.prog, &ctx.elemtyp);
+        let decoded = chunks
+            .into_par_iter()
+            .map(|(bytes, offset, elements)| {
+                let (format, options) = (ctx.format, ctx.options);
+                crate::de::decode_chunk(bytes, offset, elements, prog, elemtyp, format, options)
+            })
+            .collect::<Result<Vec<Vec<T>>>>();
+        match decoded {
+            Ok(decoded) => {
+                let mut out = Vec::with_capacity(decoded.iter().map(Vec::len).sum());
+                for chunk in decoded {
+                    out.extend(chunk);
+                }
+                Ok(out)
+            }
+            Err(err) => {
+                let msg = E::custom(&err);
+                *ctx.error.borrow_mut() = Some(err);
+                Err(msg)
+            }
+        }
+    }
+}
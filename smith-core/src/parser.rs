@@ -43,6 +43,8 @@ pub fn parse(src: &str) -> AST {
             Rule::Struct => root_declarations.0.push(Rc::new(parse_struct(rule))),
 
             Rule::Enum => root_declarations.0.push(Rc::new(parse_enum(rule))),
+
+            Rule::Flags => root_declarations.0.push(Rc::new(parse_flags(rule))),
             _ => {
                 panic!("Unexpected rule {:?}", rule.as_rule())
             }
@@ -110,6 +112,13 @@ fn parse_enum(rule: Pair<Rule>) -> ParsedEnum {
     }
 }
 
+fn parse_flags(rule: Pair<Rule>) -> ParsedFlags {
+    let mut rule = rule.into_inner();
+    let name = rule.next().unwrap().as_str().to_owned();
+    let flags = rule.map(|f| f.as_str().to_owned()).collect();
+    ParsedFlags { name, flags }
+}
+
 pub trait RootDeclaration: Debug {
     fn name(&self) -> &str;
     fn set_name(&mut self, s: String);
@@ -125,6 +134,7 @@ pub trait RootDeclaration: Debug {
 pub enum ASTRootType<'a> {
     Struct(&'a ParsedStruct),
     Enum(&'a ParsedEnum),
+    Flags(&'a ParsedFlags),
 }
 
 #[derive(Debug, Clone)]
@@ -216,9 +226,54 @@ impl RootDeclaration for ParsedEnum {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct ParsedFlags {
+    pub name: String,
+    pub flags: Vec<String>,
+}
+
+impl RootDeclaration for ParsedFlags {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_name(&mut self, s: String) {
+        self.name = s
+    }
+
+    //Flags can't be generic
+    fn generics(&self) -> &Vec<String> {
+        static NO_GENERICS: Vec<String> = Vec::new();
+        &NO_GENERICS
+    }
+
+    fn typ<'a>(&'a self) -> ASTRootType<'a> {
+        ASTRootType::Flags(self)
+    }
+
+    fn get_field_implementors(&self) -> Vec<&SmithType<String>> {
+        vec![]
+    }
+
+    fn get_field_implementors_mut(&mut self) -> Vec<&mut SmithType<String>> {
+        vec![]
+    }
+
+    fn deep_clone(&self) -> Box<dyn RootDeclaration> {
+        Box::new(self.clone())
+    }
+}
+
 pub fn parse_typ(rule: Pair<Rule>) -> SmithType<String> {
     let mut rule = rule.into_inner();
-    let typname = rule.next().unwrap().as_str();
+    let first = rule.next().unwrap();
+    if first.as_rule() == Rule::fixed_array {
+        let mut inner = first.into_inner();
+        let typ = parse_typ(inner.next().unwrap());
+        let len = inner.next().unwrap().as_str().parse().unwrap();
+        return SmithType::FixedArray(Box::new(typ), len);
+    }
+    let typname = first.as_str();
 
     let parse_generic_type = |rule: &mut Pairs<Rule>| rule.map(parse_typ).collect::<Vec<_>>();
 
@@ -233,9 +288,25 @@ pub fn parse_typ(rule: Pair<Rule>) -> SmithType<String> {
         "u32" => SmithType::U32,
         "u64" => SmithType::U64,
         "udInt" => SmithType::UInt,
+        "dInt" => SmithType::Int,
         "bool" => SmithType::Bool,
         "string" => SmithType::String,
+        "bytes" => SmithType::Bytes,
+        "timestamp" => SmithType::Timestamp,
+        "duration" => SmithType::Duration,
+        "uuid" => SmithType::Uuid,
+        "decimal" => SmithType::Decimal,
         "Array" => SmithType::Array(Box::new(parse_generic_type(&mut rule).pop().unwrap())),
+        "Option" => SmithType::Option(Box::new(parse_generic_type(&mut rule).pop().unwrap())),
+        "Map" => {
+            let mut gen = parse_generic_type(&mut rule);
+            if gen.len() != 2 {
+                panic!("Map expects 2 generic arguments (Map<K,V>) - {} provided", gen.len())
+            }
+            let val = gen.pop().unwrap();
+            let key = gen.pop().unwrap();
+            SmithType::Map(Box::new(key), Box::new(val))
+        }
         _ => SmithType::CustomType(typname.to_owned(), parse_generic_type(&mut rule)),
     };
     typ
@@ -253,12 +324,25 @@ pub enum SmithType<T> {
     U32,
     U64,
     UInt,
+    Int,
     Bool,
     String,
+    Bytes,
+
+    // Well-known types, see `wellknown` for the binary layouts
+    Timestamp,
+    Duration,
+    Uuid,
+    Decimal,
 
     // (index_in_program, generics)
     CustomType(T, Vec<SmithType<T>>),
     Array(Box<SmithType<T>>),
+    // (element type, length)
+    FixedArray(Box<SmithType<T>>, usize),
+    // (key, value)
+    Map(Box<SmithType<T>>, Box<SmithType<T>>),
+    Option(Box<SmithType<T>>),
 }
 impl SmithType<String> {
     pub fn write_self(&self, buff: &mut String) {
@@ -273,8 +357,14 @@ impl SmithType<String> {
             SmithType::U32 => buff.write_str("u32"),
             SmithType::U64 => buff.write_str("u64"),
             SmithType::UInt => buff.write_str("udInt"),
+            SmithType::Int => buff.write_str("dInt"),
             SmithType::Bool => buff.write_str("bool"),
             SmithType::String => buff.write_str("string"),
+            SmithType::Bytes => buff.write_str("bytes"),
+            SmithType::Timestamp => buff.write_str("timestamp"),
+            SmithType::Duration => buff.write_str("duration"),
+            SmithType::Uuid => buff.write_str("uuid"),
+            SmithType::Decimal => buff.write_str("decimal"),
             SmithType::CustomType(name, gen) => {
                 let r = buff.write_str(name);
                 if gen.len() > 0 {
@@ -296,6 +386,26 @@ impl SmithType<String> {
                 _ = buff.write_str(">");
                 Ok(())
             }
+            SmithType::FixedArray(t, len) => {
+                _ = buff.write_str("[");
+                t.write_self(buff);
+                _ = write!(buff, ";{len}]");
+                Ok(())
+            }
+            SmithType::Option(t) => {
+                _ = buff.write_str("Option<");
+                t.write_self(buff);
+                _ = buff.write_str(">");
+                Ok(())
+            }
+            SmithType::Map(k, v) => {
+                _ = buff.write_str("Map<");
+                k.write_self(buff);
+                _ = buff.write_char(',');
+                v.write_self(buff);
+                _ = buff.write_str(">");
+                Ok(())
+            }
         };
     }
 }
@@ -1,7 +1,7 @@
 use std::sync::atomic::Ordering;
 
 use crate::{
-    generics_engine::{SmithCustomTyp, SmithEnum, SmithProgram, SmithStruct},
+    generics_engine::{SmithCustomTyp, SmithEnum, SmithFlags, SmithProgram, SmithStruct},
     parser::{RootDeclaration, SmithType},
 };
 use crate::generics_engine::SmithCustomTypVariant;
@@ -64,6 +64,24 @@ fn resolve_custom_type(
                 variants: resolved_variants?,
             })
         }
+        SmithCustomTypVariant::Flags(f) => {
+            if f.flags.len() > SmithFlags::MAX_FLAGS {
+                return Err(format!(
+                    "Flags '{}' declares {} flags - at most {} are supported",
+                    f.name,
+                    f.flags.len(),
+                    SmithFlags::MAX_FLAGS
+                ));
+            }
+            let mut dups = f.flags.iter().enumerate().filter(|(i, n)| f.flags[..*i].contains(n));
+            if let Some((_, dup)) = dups.next() {
+                return Err(format!("Flag '{dup}' declared twice in '{}'", f.name));
+            }
+            SmithCustomTypVariant::Flags(SmithFlags {
+                name: f.name.clone(),
+                flags: f.flags.clone(),
+            })
+        }
     };
     Ok(SmithCustomTyp {
         variant: resolved,
@@ -88,9 +106,48 @@ pub fn resolve_typ(
         SmithType::F32 => SmithType::F32,
         SmithType::F64 => SmithType::F64,
         SmithType::UInt => SmithType::UInt,
+        SmithType::Int => SmithType::Int,
         SmithType::Bool => SmithType::Bool,
         SmithType::String => SmithType::String,
+        SmithType::Bytes => SmithType::Bytes,
+        SmithType::Timestamp if cfg!(feature = "timestamp") => SmithType::Timestamp,
+        SmithType::Timestamp => {
+            return Err("Type 'timestamp' requires the 'timestamp' feature of smith-core".into());
+        }
+        SmithType::Duration => SmithType::Duration,
+        SmithType::Uuid => SmithType::Uuid,
+        SmithType::Decimal => SmithType::Decimal,
         SmithType::Array(typ) => SmithType::Array(Box::new(resolve_typ(&typ, custom_types)?)),
+        SmithType::FixedArray(typ, len) => {
+            SmithType::FixedArray(Box::new(resolve_typ(typ, custom_types)?), *len)
+        }
+        SmithType::Option(typ) => SmithType::Option(Box::new(resolve_typ(typ, custom_types)?)),
+        SmithType::Map(k, v) => {
+            //Keys have to be representable as json object keys
+            if !matches!(
+                **k,
+                SmithType::String
+                    | SmithType::I8
+                    | SmithType::I16
+                    | SmithType::I32
+                    | SmithType::U8
+                    | SmithType::U16
+                    | SmithType::U32
+                    | SmithType::U64
+                    | SmithType::UInt
+                    | SmithType::Int
+            ) {
+                let mut name = String::new();
+                k.write_self(&mut name);
+                return Err(format!(
+                    "Map key type '{name}' not supported - only strings and integers can be keys"
+                ));
+            }
+            SmithType::Map(
+                Box::new(resolve_typ(k, custom_types)?),
+                Box::new(resolve_typ(v, custom_types)?),
+            )
+        }
         SmithType::CustomType(_name, gen) => {
             let mut name = String::with_capacity(50);
             typ.write_self(&mut name);
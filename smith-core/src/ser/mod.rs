@@ -1,13 +1,24 @@
-use std::{todo, println};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::Arc;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::ser::SerializeSeq;
 use serde::{ser, Serialize};
 
 use crate::ser::serialize_struct::StructEnumSerializer;
-use crate::{smith_serde, SmithType};
+use crate::format::{
+    is_header_byte, EncodeMode, EncodeOptions, Endianness, ParallelOptions, StringEncoding,
+    WireFormat,
+};
+use crate::generics_engine::{SmithCustomTypVariant, SmithFlags};
+use crate::{par, smith_serde, wellknown, SmithType};
 use rayon::prelude::*;
-use serialize_struct::{EnumSerializer, StructSerializer};
-use smith_serde::{Error, Result};
+use serialize_struct::{EnumSerializer, MapSerializer, StructSerializer};
+use string_serializer::StringSerializer;
+use smith_serde::{root_name, Error, Result};
 
 use super::resolver::ResolvedSmithProgram;
 
@@ -22,6 +33,7 @@ pub trait AsyncSerializer: ser::Serializer {
 
 macro_rules! require_type {
     ($Path:path, $Self:ident) => {{
+        $Self.unwrap_option()?;
         if let $Path = $Self.current_type {
         } else {
             return Err(Error::MissmatchedType {
@@ -32,86 +44,511 @@ macro_rules! require_type {
     }};
 }
 
+/// Bytes of a fixed-width number in the byte order of the wire format
+macro_rules! fixed_bytes {
+    ($Self:ident, $V:expr) => {{
+        let v = $V;
+        match $Self.format.endianness {
+            Endianness::Big => v.to_be_bytes(),
+            Endianness::Little => v.to_le_bytes(),
+        }
+    }};
+}
+
 macro_rules! serialize_number {
     ($Self:ident, $V:ident) => {
+        $Self.unwrap_option()?;
         match $Self.current_type {
-            SmithType::I8 => $Self.buff.extend_from_slice(
-                &i8::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::I16 => $Self.buff.extend_from_slice(
-                &i16::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::I32 => $Self.buff.extend_from_slice(
-                &i32::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::F32 => $Self.buff.extend_from_slice(&($V as f32).to_be_bytes()),
-            SmithType::F64 => $Self.buff.extend_from_slice(&($V as f64).to_be_bytes()),
-            SmithType::U8 => $Self.buff.extend_from_slice(
-                &u8::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::U16 => $Self.buff.extend_from_slice(
-                &u16::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::U32 => $Self.buff.extend_from_slice(
-                &u32::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
-            SmithType::U64 => $Self.buff.extend_from_slice(
-                &u64::try_from($V)
-                    .map_err(|_| Error::TryFromIntError)?
-                    .to_be_bytes(),
-            ),
+            SmithType::I8 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    i8::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::I16 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    i16::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::I32 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    i32::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::F32 => $Self.write_bytes(&fixed_bytes!($Self, $V as f32))?,
+            SmithType::F64 => $Self.write_bytes(&fixed_bytes!($Self, $V as f64))?,
+            SmithType::U8 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    u8::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::U16 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    u16::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::U32 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    u32::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
+            SmithType::U64 => $Self.write_bytes(
+                &fixed_bytes!(
+                    $Self,
+                    u64::try_from($V).map_err(|_| Error::TryFromIntError)?
+                ),
+            )?,
             SmithType::UInt => {
-                Dynum::encode_into(
-                    u64::try_from($V).map_err(|_| Error::TryFromIntError)?,
-                    |v| $Self.buff.push(v),
-                )
-                .map_err(Error::DynumError)?;
+                $Self.write_udint(u64::try_from($V).map_err(|_| Error::TryFromIntError)?)?;
+            }
+            SmithType::Int => {
+                $Self.write_dint(i64::try_from($V).map_err(|_| Error::TryFromIntError)?)?;
+            }
+            SmithType::Decimal => {
+                $Self.write_decimal(i64::try_from($V).map_err(|_| Error::TryFromIntError)?, 0)?;
+            }
+            //Bits of a flags type (e.g. a bitflags struct)
+            SmithType::CustomType(id, _) => {
+                let flags = $Self.flags_type(*id)?;
+                $Self.write_flags(flags, u64::try_from($V).map_err(|_| Error::TryFromIntError)?)?;
             }
             _ => {
-                panic!("Expected number")
+                return Err(Error::MissmatchedType {
+                    expected: format!("{:?}", $Self.current_type),
+                    received: "number",
+                });
             }
         };
     };
 }
 
-pub struct Serializer<'a> {
-    pub buff: Vec<u8>,
+/// Writer that only counts the bytes written to it, see [crate::Smith::encoded_len]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ByteCounter(pub usize);
+
+impl Write for ByteCounter {
+    #[inline(always)]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the binary representation of serde values into `W` - an in-memory buffer by default.
+/// The output is written in small pieces, wrap unbuffered writers (files, sockets) in a `BufWriter`
+pub struct Serializer<'a, W = Vec<u8>> {
+    pub buff: W,
     prog: &'a ResolvedSmithProgram,
     current_type: &'a SmithType<usize>,
+    format: WireFormat,
+    options: EncodeOptions,
+    //Bytes written so far, errors report it in [Error::Context]
+    written: usize,
+    //Set while an entry of a map given as array of pairs is serialized - see `serialize_seq`
+    map_entry: bool,
+    //Program handed to the workers encoding [par::ParSlice] arrays, see `with_shared_program`
+    shared_prog: Option<Arc<ResolvedSmithProgram>>,
+    //Set by `write_header` for the default format until the first byte is written
+    header_pending: bool,
 }
 
 impl<'a> Serializer<'a> {
     pub fn new(prog: &'a ResolvedSmithProgram, typ: &'a SmithType<usize>) -> Self {
+        Self::from_writer(prog, typ, Vec::with_capacity(1048))
+    }
+
+    /// Starts the next message: clears the buffer, sets the root type and writes the format header.
+    /// The allocation of the buffer is kept - once it has grown to the message size,
+    /// encoding structs, strings, arrays, options and enums doesn't allocate
+    pub fn reset(&mut self, typ: &'a SmithType<usize>) {
+        self.buff.clear();
+        self.current_type = typ;
+        if let Some(header) = self.format.header() {
+            self.buff.push(header);
+        }
+        self.header_pending = self.format.header().is_none();
+        self.written = self.buff.len();
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buff
+    }
+
+    pub fn buffer(self) -> Vec<u8> {
+        self.buff
+    }
+}
+
+impl<'a, W: Write> Serializer<'a, W> {
+    pub fn from_writer(
+        prog: &'a ResolvedSmithProgram,
+        typ: &'a SmithType<usize>,
+        writer: W,
+    ) -> Self {
         Self {
-            buff: Vec::with_capacity(1048),
+            buff: writer,
             prog,
             current_type: typ,
+            format: WireFormat::default(),
+            options: EncodeOptions::default(),
+            written: 0,
+            map_entry: false,
+            shared_prog: None,
+            header_pending: false,
         }
     }
 
-    pub fn copy_context(&self) -> Self {
-        Self {
+    /// Encodes with `prog`, which is shared with the workers encoding [par::ParSlice] arrays -
+    /// without it those are encoded sequentially
+    pub fn with_shared_program(mut self, prog: &'a Arc<ResolvedSmithProgram>) -> Self {
+        self.prog = prog;
+        self.shared_prog = Some(prog.clone());
+        self
+    }
+
+    pub fn with_format(mut self, format: WireFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_options(mut self, options: EncodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// In-memory serializer sharing program, type and format - used to buffer parts of the output.
+    /// Offsets of its errors continue from the current position
+    pub fn copy_context(&self) -> Serializer<'a> {
+        Serializer {
             buff: Vec::with_capacity(128),
             prog: self.prog,
             current_type: self.current_type,
+            format: self.format,
+            options: self.options,
+            written: self.written,
+            map_entry: false,
+            shared_prog: self.shared_prog.clone(),
+            header_pending: false,
         }
     }
 
-    pub fn buffer(self) -> Vec<u8> {
+    /// Position in the output, errors report it in [Error::Context]
+    pub fn offset(&self) -> usize {
+        self.written
+    }
+
+    /// Adds the name of the root type `typ` to the path of `err`
+    pub(crate) fn in_root(&self, err: Error, typ: &SmithType<usize>) -> Error {
+        err.within(root_name(typ, self.prog), self.written)
+    }
+
+    /// Records non-default wire formats at the start of the message, see [WireFormat::header].
+    /// Default messages get [WireFormat::DEFAULT_HEADER] if their first byte is a header byte
+    pub fn write_header(&mut self) -> Result<()> {
+        match self.format.header() {
+            Some(header) => self.write_byte(header),
+            None => {
+                self.header_pending = true;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
         self.buff
     }
+
+    #[inline(always)]
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.header_pending && !bytes.is_empty() {
+            self.header_pending = false;
+            if is_header_byte(bytes[0]) {
+                self.write_byte(WireFormat::DEFAULT_HEADER)?;
+            }
+        }
+        self.buff.write_all(bytes).map_err(Error::Io)?;
+        self.written += bytes.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.write_bytes(&[byte])
+    }
+
+    #[inline(always)]
+    fn write_udint(&mut self, v: u64) -> Result<()> {
+        let mut bytes = [0; 8];
+        let mut len = 0;
+        Dynum::encode_into(v, |b| {
+            bytes[len] = b;
+            len += 1;
+        })
+        .map_err(Error::DynumError)?;
+        self.write_bytes(&bytes[..len])
+    }
+
+    #[inline(always)]
+    fn write_dint(&mut self, v: i64) -> Result<()> {
+        let mut bytes = [0; 8];
+        let mut len = 0;
+        Dynum::encode_signed_into(v, |b| {
+            bytes[len] = b;
+            len += 1;
+        })
+        .map_err(Error::DynumError)?;
+        self.write_bytes(&bytes[..len])
+    }
+
+    #[inline(always)]
+    fn write_decimal(&mut self, mantissa: i64, scale: u8) -> Result<()> {
+        self.write_dint(mantissa)?;
+        self.write_byte(scale)
+    }
+
+    /// Writes the zero value of `typ`, used for missing struct fields - see [crate::MissingFields]
+    pub(crate) fn write_default(&mut self, typ: &SmithType<usize>, depth: usize) -> Result<()> {
+        //Types containing themselves without Option / Array in between have no finite default
+        if depth > 64 {
+            return Err(Error::Static("Recursive type without a finite default value"));
+        }
+        match typ {
+            SmithType::I8 | SmithType::U8 | SmithType::Bool => self.write_byte(0),
+            SmithType::I16 | SmithType::U16 => self.write_bytes(&[0; 2]),
+            SmithType::I32 | SmithType::U32 | SmithType::F32 => self.write_bytes(&[0; 4]),
+            SmithType::U64 | SmithType::F64 => self.write_bytes(&[0; 8]),
+            SmithType::UInt | SmithType::Bytes | SmithType::Array(_) | SmithType::Map(_, _) => {
+                self.write_udint(0)
+            }
+            SmithType::Int => self.write_dint(0),
+            SmithType::String => match self.format.strings {
+                StringEncoding::NulTerminated => self.write_byte(0),
+                StringEncoding::LengthPrefixed => self.write_udint(0),
+            },
+            SmithType::Option(_) => self.write_byte(0),
+            SmithType::Timestamp => {
+                self.write_dint(0)?;
+                self.write_udint(0)
+            }
+            SmithType::Duration => {
+                self.write_udint(0)?;
+                self.write_udint(0)
+            }
+            SmithType::Uuid => self.write_bytes(&[0; 16]),
+            SmithType::Decimal => self.write_decimal(0, 0),
+            SmithType::FixedArray(elemtyp, len) => {
+                for _ in 0..*len {
+                    self.write_default(elemtyp, depth + 1)?;
+                }
+                Ok(())
+            }
+            SmithType::CustomType(id, _) => {
+                let custom = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+                match &custom.variant {
+                    SmithCustomTypVariant::Struct(s) => {
+                        for (_, t) in &s.fields {
+                            self.write_default(t, depth + 1)?;
+                        }
+                        Ok(())
+                    }
+                    SmithCustomTypVariant::Enum(e) => {
+                        let (_, payload) = e
+                            .variants
+                            .first()
+                            .ok_or(Error::Static("Enum without variants has no default"))?;
+                        self.write_udint(0)?;
+                        match payload {
+                            Some(t) => self.write_default(t, depth + 1),
+                            None => Ok(()),
+                        }
+                    }
+                    SmithCustomTypVariant::Flags(f) => {
+                        for _ in 0..f.byte_len() {
+                            self.write_byte(0)?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Unit values are only valid for structs without fields - they take zero bytes
+    fn require_empty_struct(&mut self, received: &'static str) -> Result<()> {
+        self.unwrap_option()?;
+        if let SmithType::CustomType(id, _) = self.current_type {
+            let typ = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
+            if typ.as_struct().is_some_and(|s| s.fields.is_empty()) {
+                return Ok(());
+            }
+        }
+        Err(Error::MissmatchedType {
+            expected: format!("{:?}", self.current_type),
+            received,
+        })
+    }
+
+    fn flags_type(&self, id: usize) -> Result<&'a SmithFlags> {
+        let typ = self.prog.get(id).ok_or(Error::CustomTypeNotFoundById)?;
+        typ.as_flags().ok_or_else(|| Error::MissmatchedType {
+            expected: typ.get_name().to_owned(),
+            received: "flags",
+        })
+    }
+
+    fn write_flags(&mut self, flags: &SmithFlags, mask: u64) -> Result<()> {
+        if mask & !flags.all() != 0 {
+            return Err(Error::UnknownFlag(format!("{:#x}", mask & !flags.all())));
+        }
+        self.write_bytes(&mask.to_le_bytes()[..flags.byte_len()])?;
+        Ok(())
+    }
+
+    /// Well-known types are provided as their canonical string, see [wellknown]
+    fn serialize_wellknown_str(&mut self, v: &str) -> Result<()> {
+        match self.current_type {
+            SmithType::Timestamp => {
+                let (secs, nanos) = wellknown::parse_timestamp(v)?;
+                self.write_dint(secs)?;
+                self.write_udint(nanos as u64)
+            }
+            SmithType::Duration => {
+                let (secs, nanos) = wellknown::parse_duration(v)?;
+                self.write_udint(secs)?;
+                self.write_udint(nanos as u64)
+            }
+            SmithType::Uuid => {
+                self.write_bytes(&wellknown::parse_uuid(v)?)?;
+                Ok(())
+            }
+            SmithType::Decimal => {
+                let (mantissa, scale) = wellknown::parse_decimal(v)?;
+                self.write_decimal(mantissa, scale)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Writes the udInt length and the elements, with `parallel` arrays of at least `min_len`
+    /// elements are encoded in chunks on the rayon thread pool.
+    /// With [ParallelOptions::chunk_table] those are written as
+    /// `marker, length, chunk count, (elements, bytes) per chunk, elements` -
+    /// see [par::CHUNK_TABLE_MARKER]
+    pub(crate) fn write_par_slice<T: Serialize + Sync>(
+        &mut self,
+        elemtyp: &'a SmithType<usize>,
+        seq: &[T],
+        parallel: Option<ParallelOptions>,
+    ) -> Result<()> {
+        let Some(parallel) = parallel.filter(|p| seq.len() >= p.min_len) else {
+            self.write_udint(seq.len() as u64)?;
+            for (i, val) in seq.iter().enumerate() {
+                self.current_type = elemtyp;
+                val.serialize(&mut *self)
+                    .map_err(|e| e.within(format_args!("[{i}]"), self.written))?;
+            }
+            return Ok(());
+        };
+
+        let context = self.copy_context();
+        let chunk_size = parallel.chunk_size_for(seq.len());
+        let chunks = seq
+            .par_chunks(chunk_size)
+            .enumerate()
+            .map(|(n, chunk)| {
+                //Offsets are relative to the chunk
+                let mut serializer = context.copy_context();
+                serializer.written = 0;
+                for (i, val) in chunk.iter().enumerate() {
+                    serializer.current_type = elemtyp;
+                    val.serialize(&mut serializer).map_err(|e| {
+                        e.within(format_args!("[{}]", n * chunk_size + i), serializer.written)
+                    })?;
+                }
+                Ok((chunk.len(), serializer.buff))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if parallel.chunk_table {
+            self.write_udint(par::CHUNK_TABLE_MARKER)?;
+            self.write_udint(seq.len() as u64)?;
+            self.write_udint(chunks.len() as u64)?;
+            for (elements, bytes) in &chunks {
+                self.write_udint(*elements as u64)?;
+                self.write_udint(bytes.len() as u64)?;
+            }
+        } else {
+            self.write_udint(seq.len() as u64)?;
+        }
+        for (_, bytes) in chunks {
+            self.write_bytes(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Inner value of a [par::ParSlice] - the slice encodes itself in parallel if enabled and
+    /// hands back the encoded array
+    fn serialize_par<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.unwrap_option()?;
+        let (SmithType::Array(elemtyp), Some(parallel), Some(prog)) =
+            (self.current_type, self.options.parallel, &self.shared_prog)
+        else {
+            return value.serialize(self);
+        };
+        let output = Rc::new(RefCell::new(None));
+        let context = par::ParContext {
+            prog: prog.clone(),
+            elemtyp: (**elemtyp).clone(),
+            format: self.format,
+            options: self.options,
+            parallel,
+            output: output.clone(),
+        };
+        let result = {
+            let _guard = par::enter(context);
+            value.serialize(&mut *self)
+        };
+        match output.take() {
+            Some(Ok(bytes)) => result.and_then(|_| self.write_bytes(&bytes)),
+            Some(Err(err)) => Err(err),
+            //Encoded as plain sequence
+            None => result,
+        }
+    }
+
+    /// A plain value provided for an `Option<T>` schema type is present - the presence byte `1` gets
+    /// written and the value is serialized as `T`
+    #[inline(always)]
+    /// Entries of maps given as array of pairs have to be `[key, value]` sequences
+    fn reject_map_entry(&mut self, received: &'static str) -> Result<()> {
+        if std::mem::take(&mut self.map_entry) {
+            return Err(Error::MissmatchedType {
+                expected: "[key, value] pair".to_owned(),
+                received,
+            });
+        }
+        Ok(())
+    }
+
+    fn unwrap_option(&mut self) -> Result<()> {
+        while let SmithType::Option(inner) = self.current_type {
+            self.write_byte(1)?;
+            self.current_type = inner;
+        }
+        Ok(())
+    }
 }
 
 pub fn to_binary<T>(
@@ -124,32 +561,17 @@ where
 {
     let mut serializer = Serializer::new(prog, typ);
     value.serialize(&mut serializer)?;
-    Ok(serializer.buff.into_boxed_slice())
+    Ok(serializer.buffer().into_boxed_slice())
 }
 
 
 
-impl<'a, 'b> AsyncSerializer for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> AsyncSerializer for &'a mut Serializer<'b, W> {
+    /// Uses the [ParallelOptions] of the serializer, sequential if parallel encoding isn't enabled
     fn serialize_slice_async<T: Serialize + Sync>(self, seq: &[T]) -> Result<()> {
+        self.unwrap_option()?;
         if let SmithType::Array(elemtyp) = self.current_type {
-            self.current_type = &SmithType::UInt;
-            seq.len().serialize(&mut *self)?;
-
-            let mut arrbuff = seq
-                .par_chunks(seq.len() / num_cpus::get())
-                .map(|chunk| {
-                    let mut serializer = self.copy_context();
-                    for val in chunk {
-                        serializer.current_type = elemtyp;
-                        val.serialize(&mut serializer).unwrap();
-                    }
-                    serializer.buff
-                })
-                .flatten()
-                .collect::<Vec<_>>();
-
-            self.buff.append(&mut arrbuff);
-            Ok(())
+            self.write_par_slice(elemtyp, seq, self.options.parallel)
         } else {
             Err(Error::MissmatchedType {
                 expected: format!("{:?}", self.current_type),
@@ -159,23 +581,24 @@ impl<'a, 'b> AsyncSerializer for &'a mut Serializer<'b> {
     }
 }
 
-impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> ser::Serializer for &'a mut Serializer<'b, W> {
     type Ok = ();
 
     type Error = Error;
 
-    type SerializeSeq = SeqSerializer<'a, 'b>;
-    type SerializeTuple = Self;
+    type SerializeSeq = SeqSerializer<'a, 'b, W>;
+    type SerializeTuple = SeqSerializer<'a, 'b, W>;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
 
-    type SerializeStruct = StructEnumSerializer<'a, 'b>;
-    type SerializeMap = StructEnumSerializer<'a, 'b>;
+    type SerializeStruct = StructEnumSerializer<'a, 'b, W>;
+    type SerializeMap = StructEnumSerializer<'a, 'b, W>;
     type SerializeStructVariant = Self;
     #[inline(always)]
     fn serialize_bool(self, v: bool) -> Result<()> {
+        self.unwrap_option()?;
         if let SmithType::Bool = self.current_type {
-            self.buff.push((v as u8).to_be());
+            self.write_byte((v as u8).to_be())?;
             Ok(())
         } else {
             Err(Error::MissmatchedType {
@@ -201,7 +624,8 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     }
     #[inline(always)]
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.serialize_i32(v.try_into().map_err(|_| Error::I64CastI32Failed)?)
+        serialize_number!(self, v);
+        Ok(())
     }
     #[inline(always)]
     fn serialize_u8(self, v: u8) -> Result<()> {
@@ -225,9 +649,10 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     }
     #[inline(always)]
     fn serialize_f32(self, v: f32) -> Result<()> {
+        self.unwrap_option()?;
         match self.current_type {
-            SmithType::F32 => self.buff.extend_from_slice(&v.to_be_bytes()),
-            SmithType::F64 => self.buff.extend_from_slice(&(v as f64).to_be_bytes()),
+            SmithType::F32 => self.write_bytes(&fixed_bytes!(self, v))?,
+            SmithType::F64 => self.write_bytes(&fixed_bytes!(self, v as f64))?,
             _ => {
                 return Err(Error::MissmatchedType {
                     expected: "Float".to_string(),
@@ -239,9 +664,12 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     }
     #[inline(always)]
     fn serialize_f64(self, v: f64) -> Result<()> {
+        self.unwrap_option()?;
         match self.current_type {
-            SmithType::F32 => self.buff.extend_from_slice(&(v as f32).to_be_bytes()),
-            SmithType::F64 => self.buff.extend_from_slice(&v.to_be_bytes()),
+            SmithType::F32 => self.write_bytes(&fixed_bytes!(self, v as f32))?,
+            SmithType::F64 => self.write_bytes(&fixed_bytes!(self, v))?,
+            //Json numbers arrive as float - the shortest representation is the decimal one
+            SmithType::Decimal if v.is_finite() => self.serialize_wellknown_str(&v.to_string())?,
             _ => {
                 return Err(Error::MissmatchedType {
                     expected: "Float".to_string(),
@@ -257,9 +685,44 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     }
     #[inline(always)]
     fn serialize_str(self, v: &str) -> Result<()> {
+        self.unwrap_option()?;
+        if let SmithType::Bytes = self.current_type {
+            //Bytes are represented as base64 string inside json
+            let bytes = BASE64.decode(v).map_err(Error::InvalidBase64)?;
+            return self.serialize_bytes(&bytes);
+        }
+        if let SmithType::Timestamp
+        | SmithType::Duration
+        | SmithType::Uuid
+        | SmithType::Decimal = self.current_type
+        {
+            return self.serialize_wellknown_str(v);
+        }
+        //Flags as string: "Read | Write"
+        if let SmithType::CustomType(id, _) = self.current_type {
+            let flags = self.flags_type(*id)?;
+            let mut mask = 0;
+            for name in v.split('|').map(str::trim).filter(|n| !n.is_empty()) {
+                mask |= flags.bit(name).ok_or_else(|| Error::UnknownFlag(name.to_owned()))?;
+            }
+            return self.write_flags(flags, mask);
+        }
         require_type!(SmithType::String, self);
-        self.buff.extend_from_slice(v.as_bytes());
-        self.buff.push(0);
+        match self.format.strings {
+            StringEncoding::NulTerminated => {
+                if v.as_bytes().contains(&0) {
+                    return Err(Error::Static(
+                        "Nul terminated strings can't contain '\\0' - use length prefixed strings",
+                    ));
+                }
+                self.write_bytes(v.as_bytes())?;
+                self.write_byte(0)?;
+            }
+            StringEncoding::LengthPrefixed => {
+                self.write_udint(v.len() as u64)?;
+                self.write_bytes(v.as_bytes())?;
+            }
+        }
         Ok(())
     }
     #[inline(always)]
@@ -267,15 +730,30 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     where
         T: ?Sized + Serialize,
     {
+        if let SmithType::Option(inner) = self.current_type {
+            self.write_byte(1)?;
+            self.current_type = inner;
+        }
         value.serialize(self)
     }
+    /// Absent values are written as a single `0` byte
     #[inline(always)]
     fn serialize_none(self) -> Result<()> {
-        Ok(())
+        if let SmithType::Option(_) = self.current_type {
+            self.write_byte(0)?;
+            Ok(())
+        } else {
+            //json null of an untyped SmithValue
+            self.require_empty_struct("none")
+        }
     }
 
     fn serialize_unit(self) -> Result<()> {
-        unimplemented!("SMITH does not support serialization of empty unit data")
+        //json null
+        if let SmithType::Option(_) = self.current_type {
+            return self.serialize_none();
+        }
+        self.require_empty_struct("unit")
     }
 
     /*
@@ -312,29 +790,47 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     where
         T: ?Sized + Serialize,
     {
+        self.unwrap_option()?;
         if let SmithType::CustomType(id, _) = self.current_type {
             let s = self
                 .prog
                 .get(*id)
                 .ok_or(Error::CustomTypeNotFoundById)?
                 .as_enum()
-                .ok_or(Error::MissmatchedType {
+                .ok_or_else(|| Error::MissmatchedType {
                     expected: "enum".to_owned(),
                     received: "struct",
                 })?;
 
+            let by_name = match self.options.mode {
+                EncodeMode::Lenient => s.variants.iter().position(|v| v.0 == variant),
+                EncodeMode::Strict => None,
+            };
+            let index = by_name.unwrap_or(_variant_index as usize);
             let variantdata = s
                 .variants
-                .get(_variant_index as usize)
+                .get(index)
                 .ok_or(Error::Static("Enum variants count not matching"))?;
             if variant != variantdata.0 {
-                println!("WARNING: for enum '{_name}' - provided variant '{variant}' name does not match with '{}' at index {_variant_index}", variantdata.0)
+                if self.options.mode == EncodeMode::Strict {
+                    return Err(Error::EnumVariantWrongName {
+                        expected: variantdata.0.clone(),
+                        received: variant.to_owned(),
+                    });
+                }
+                log::warn!(
+                    "enum '{}' has no variant '{variant}' - encoded as '{}' (index {index})",
+                    s.name,
+                    variantdata.0
+                );
             }
             self.current_type = &SmithType::UInt;
-            _variant_index.serialize(&mut *self)?;
+            index.serialize(&mut *self)?;
             if let Some(s) = &variantdata.1 {
                 self.current_type = s;
-                value.serialize(&mut *self)?;
+                value.serialize(&mut *self).map_err(|e| {
+                    e.within(format_args!("<{}>", variantdata.0), self.written)
+                })?;
             }
             return Ok(());
         } else {
@@ -372,28 +868,95 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
      */
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        if let SmithType::Array(elemtyp) = self.current_type {
-            if let Some(_len) = _len {
-                self.current_type = &SmithType::UInt;
-                _len.serialize(&mut *self)?
-            } else {
-                return Err(Error::ExpectedArrayLen);
+        self.unwrap_option()?;
+        let map_entry = std::mem::take(&mut self.map_entry);
+        let mut entries = false;
+        let elemtyp = match self.current_type {
+            SmithType::Array(elemtyp) => elemtyp,
+            //Key and value of a map entry given as pair
+            SmithType::Map(keytyp, valtyp) if map_entry => {
+                if let Some(len) = _len.filter(|len| *len != 2) {
+                    return Err(Error::FixedArrayLenMismatch {
+                        expected: 2,
+                        received: len,
+                    });
+                }
+                return Ok(SeqSerializer {
+                    elemtyp: keytyp,
+                    serializer: self,
+                    fixed_len: Some(2),
+                    len: 0,
+                    flags: None,
+                    entries: false,
+                    entry_value: Some(valtyp),
+                });
             }
-
-            Ok(SeqSerializer {
-                elemtyp,
-                serializer: self,
-            })
+            //Maps given as array of [key, value] pairs, e.g. json with integer keys
+            SmithType::Map(_, _) => {
+                entries = true;
+                self.current_type
+            }
+            //Vec<u8> & co are passed as sequence by serde - bytes share the layout of Array<u8>
+            SmithType::Bytes => &SmithType::U8,
+            //Length is part of the schema - no prefix
+            SmithType::FixedArray(elemtyp, fixed_len) => {
+                if let Some(len) = _len.filter(|len| len != fixed_len) {
+                    return Err(Error::FixedArrayLenMismatch {
+                        expected: *fixed_len,
+                        received: len,
+                    });
+                }
+                return Ok(SeqSerializer {
+                    elemtyp,
+                    serializer: self,
+                    fixed_len: Some(*fixed_len),
+                    len: 0,
+                    flags: None,
+                    entries: false,
+                    entry_value: None,
+                });
+            }
+            //List of flag names - the mask is written at the end
+            SmithType::CustomType(id, _) => {
+                let flags = self.flags_type(*id)?;
+                return Ok(SeqSerializer {
+                    elemtyp: &SmithType::String,
+                    serializer: self,
+                    fixed_len: None,
+                    len: 0,
+                    flags: Some((flags, 0)),
+                    entries: false,
+                    entry_value: None,
+                });
+            }
+            _ => {
+                return Err(Error::MissmatchedType {
+                    expected: format!("{:?}", self.current_type),
+                    received: "Array",
+                })
+            }
+        };
+        if let Some(_len) = _len {
+            self.current_type = &SmithType::UInt;
+            _len.serialize(&mut *self)?
         } else {
-            Err(Error::MissmatchedType {
-                expected: format!("{:?}", self.current_type),
-                received: "Array",
-            })
+            return Err(Error::ExpectedArrayLen);
         }
+
+        Ok(SeqSerializer {
+            elemtyp,
+            serializer: self,
+            fixed_len: None,
+            len: 0,
+            flags: None,
+            entries,
+            entry_value: None,
+        })
     }
 
+    /// Rust arrays ([T; N]) are serialized as tuple by serde
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        unimplemented!()
+        self.serialize_seq(Some(_len))
     }
 
     // Tuple structs look just like sequences in JSON.
@@ -405,9 +968,40 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
         unimplemented!()
     }
 
+    /// Bytes are prefixed with their length as udInt - Array<u8> shares the same layout
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        println!("WARNING Serializing bytes directly is highly dangerous!");
-        self.buff.extend_from_slice(v);
+        self.unwrap_option()?;
+        match self.current_type {
+            SmithType::Bytes => {}
+            SmithType::Array(t) if **t == SmithType::U8 => {}
+            SmithType::Uuid => {
+                let uuid: [u8; 16] = v.try_into().map_err(|_| Error::FixedArrayLenMismatch {
+                    expected: 16,
+                    received: v.len(),
+                })?;
+                self.write_bytes(&uuid)?;
+                return Ok(());
+            }
+            SmithType::FixedArray(t, len) if **t == SmithType::U8 => {
+                if v.len() != *len {
+                    return Err(Error::FixedArrayLenMismatch {
+                        expected: *len,
+                        received: v.len(),
+                    });
+                }
+                self.write_bytes(v)?;
+                return Ok(());
+            }
+            _ => {
+                return Err(Error::MissmatchedType {
+                    expected: format!("{:?}", self.current_type),
+                    received: "bytes",
+                })
+            }
+        }
+        self.current_type = &SmithType::UInt;
+        v.len().serialize(&mut *self)?;
+        self.write_bytes(v)?;
         Ok(())
     }
 
@@ -416,29 +1010,39 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     SECTION STRUCTS
     ================
      */
-    // Maps are represented in JSON as `{ K: V, K: V, ... }`.
+    /// Maps are written as udInt length followed by the key/value pairs.
+    /// Maps on a struct/enum schema type are treated as struct (json objects)
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.unwrap_option()?;
+        self.reject_map_entry("map")?;
+        if let SmithType::Map(keytyp, valtyp) = self.current_type {
+            return Ok(StructEnumSerializer::newmap(MapSerializer::new(
+                self, keytyp, valtyp, _len,
+            )?));
+        }
         self.serialize_struct("", 0)
     }
 
     /// Ignores the name of given struct - only name of schema matters
     fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        self.unwrap_option()?;
+        if let Some(structyp) = wellknown::struct_of(self.current_type) {
+            return Ok(StructEnumSerializer::newstruct(StructSerializer::new(structyp, self)));
+        }
         if let SmithType::CustomType(id, _gen) = self.current_type {
             let typ = self.prog.get(*id).ok_or(Error::CustomTypeNotFoundById)?;
             match &typ.variant {
                 crate::generics_engine::SmithCustomTypVariant::Struct(s) => {
-                    return Ok(StructEnumSerializer::newstruct(StructSerializer {
-                        structyp: s,
-                        serializer: self,
-                        current_field_idx: 0,
-                    }));
+                    return Ok(StructEnumSerializer::newstruct(StructSerializer::new(s, self)));
                 }
                 crate::generics_engine::SmithCustomTypVariant::Enum(e) => {
-                    return Ok(StructEnumSerializer::newenum(EnumSerializer {
-                        serializer: self,
-                        smith_enum: e,
-                        enum_variant_type: None,
-                    }));
+                    return Ok(StructEnumSerializer::newenum(EnumSerializer::new(e, self)));
+                }
+                crate::generics_engine::SmithCustomTypVariant::Flags(f) => {
+                    Err(Error::MissmatchedType {
+                        expected: f.name.clone(),
+                        received: "struct",
+                    })
                 }
             }
         } else {
@@ -450,27 +1054,42 @@ impl<'a, 'b> ser::Serializer for &'a mut Serializer<'b> {
     }
     /// EXAMPLE: struct Test;
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
-        self.serialize_struct(_name, 0)?;
-        Ok(())
+        //Array encoded by a ParSlice, written by `serialize_par`
+        if _name == par::TOKEN {
+            return Ok(());
+        }
+        self.require_empty_struct("unit struct")
     }
 
-    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<()>
+    /// EXAMPLE: struct UserId(u64);
+    /// Newtypes are transparent - the inner value is serialized as the schema type
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!("SMITH does not support serialization of newtype structs")
+        if _name == par::TOKEN {
+            return self.serialize_par(value);
+        }
+        value.serialize(self)
     }
 }
 
 
-pub struct SeqSerializer<'a, 'b> {
+pub struct SeqSerializer<'a, 'b, W = Vec<u8>> {
     pub elemtyp: &'b SmithType<usize>,
-    pub serializer: &'a mut Serializer<'b>,
+    pub serializer: &'a mut Serializer<'b, W>,
+    //Length of FixedArray schema types, validated on every element
+    pub fixed_len: Option<usize>,
+    pub len: usize,
+    //Flags type and the mask collected so far, elements are the flag names
+    pub flags: Option<(&'b SmithFlags, u64)>,
+    //Elements are the [key, value] pairs of the map `elemtyp`
+    pub entries: bool,
+    //Value type of a single [key, value] pair, `elemtyp` is the key type
+    pub entry_value: Option<&'b SmithType<usize>>,
 }
 
-
-
-impl<'a, 'b> ser::SerializeSeq for SeqSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> ser::SerializeSeq for SeqSerializer<'a, 'b, W> {
     type Ok = ();
     type Error = Error;
 
@@ -478,16 +1097,46 @@ impl<'a, 'b> ser::SerializeSeq for SeqSerializer<'a, 'b> {
     where
         T: ?Sized + Serialize,
     {
-        self.serializer.current_type = self.elemtyp;
-        value.serialize(&mut *self.serializer)
+        self.len += 1;
+        if let Some((flags, mask)) = &mut self.flags {
+            let name = StringSerializer::get(value)?;
+            *mask |= flags.bit(&name).ok_or(Error::UnknownFlag(name))?;
+            return Ok(());
+        }
+        if let Some(fixed_len) = self.fixed_len.filter(|l| self.len > *l) {
+            return Err(Error::FixedArrayLenMismatch {
+                expected: fixed_len,
+                received: self.len,
+            });
+        }
+        self.serializer.current_type = match self.entry_value {
+            Some(valtyp) if self.len == 2 => valtyp,
+            _ => self.elemtyp,
+        };
+        self.serializer.map_entry = self.entries;
+        let mut res = value.serialize(&mut *self.serializer);
+        //Not taken by `serialize_seq` - the entry isn't a pair
+        if self.serializer.map_entry {
+            res = self.serializer.reject_map_entry("value");
+        }
+        res.map_err(|e| e.within(format_args!("[{}]", self.len - 1), self.serializer.written))
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        if let Some((flags, mask)) = self.flags {
+            return self.serializer.write_flags(flags, mask);
+        }
+        match self.fixed_len {
+            Some(fixed_len) if fixed_len != self.len => Err(Error::FixedArrayLenMismatch {
+                expected: fixed_len,
+                received: self.len,
+            }),
+            _ => Ok(()),
+        }
     }
 }
 
-impl<'a, 'b> ser::SerializeTuple for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> ser::SerializeTuple for SeqSerializer<'a, 'b, W> {
     type Ok = ();
     type Error = Error;
 
@@ -495,15 +1144,15 @@ impl<'a, 'b> ser::SerializeTuple for &'a mut Serializer<'b> {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        ser::SerializeSeq::serialize_element(self, value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        ser::SerializeSeq::end(self)
     }
 }
 
-impl<'a, 'b> ser::SerializeTupleStruct for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> ser::SerializeTupleStruct for &'a mut Serializer<'b, W> {
     type Ok = ();
     type Error = Error;
 
@@ -519,7 +1168,7 @@ impl<'a, 'b> ser::SerializeTupleStruct for &'a mut Serializer<'b> {
     }
 }
 
-impl<'a, 'b> ser::SerializeTupleVariant for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> ser::SerializeTupleVariant for &'a mut Serializer<'b, W> {
     type Ok = ();
     type Error = Error;
 
@@ -537,7 +1186,7 @@ impl<'a, 'b> ser::SerializeTupleVariant for &'a mut Serializer<'b> {
 
 // Similar to `SerializeTupleVariant`, here the `end` method is responsible for
 // closing both of the curly braces opened by `serialize_struct_variant`.
-impl<'a, 'b> ser::SerializeStructVariant for &'a mut Serializer<'b> {
+impl<'a, 'b, W: Write> ser::SerializeStructVariant for &'a mut Serializer<'b, W> {
     type Ok = ();
     type Error = Error;
 
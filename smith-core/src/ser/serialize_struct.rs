@@ -1,22 +1,26 @@
+use std::io::Write;
+
 use serde::{ser, Serialize};
 
 use crate::generics_engine::{SmithEnum, SmithStruct};
+use crate::format::{EncodeMode, MissingFields};
 use crate::ser::{Serializer, string_serializer};
-use crate::SmithType;
+use crate::{value, SmithType, SmithValue};
 
 use super::{Error, Result};
 
-pub struct StructEnumSerializer<'a, 'b> {
+pub struct StructEnumSerializer<'a, 'b, W = Vec<u8>> {
     curr_key: Option<String>,
-    variants: StructEnumSerializerVariants<'a, 'b>,
+    variants: StructEnumSerializerVariants<'a, 'b, W>,
 }
 
-enum StructEnumSerializerVariants<'a, 'b> {
-    Struct(StructSerializer<'a, 'b>),
-    Enum(EnumSerializer<'a, 'b>),
+enum StructEnumSerializerVariants<'a, 'b, W> {
+    Struct(StructSerializer<'a, 'b, W>),
+    Enum(EnumSerializer<'a, 'b, W>),
+    Map(MapSerializer<'a, 'b, W>),
 }
 
-impl<'a, 'b> StructEnumSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> StructEnumSerializer<'a, 'b, W> {
     #[inline(always)]
     fn serialize_field_strkey<T: ?Sized>(&mut self, key: &str, value: &T) -> Result<()>
     where
@@ -25,25 +29,36 @@ impl<'a, 'b> StructEnumSerializer<'a, 'b> {
         match &mut self.variants {
             StructEnumSerializerVariants::Struct(s) => s.serialize_field(key, value),
             StructEnumSerializerVariants::Enum(e) => e.serialize_enum(key, value),
+            StructEnumSerializerVariants::Map(m) => {
+                m.serialize_key(&key)?;
+                m.serialize_value(value)
+            }
         }
     }
 
-    pub fn newstruct(s: StructSerializer<'a, 'b>) -> Self {
+    pub fn newstruct(s: StructSerializer<'a, 'b, W>) -> Self {
         Self {
             curr_key: None,
             variants: StructEnumSerializerVariants::Struct(s),
         }
     }
 
-    pub fn newenum(e: EnumSerializer<'a, 'b>) -> Self {
+    pub fn newenum(e: EnumSerializer<'a, 'b, W>) -> Self {
         Self {
             curr_key: None,
             variants: StructEnumSerializerVariants::Enum(e),
         }
     }
+
+    pub fn newmap(m: MapSerializer<'a, 'b, W>) -> Self {
+        Self {
+            curr_key: None,
+            variants: StructEnumSerializerVariants::Map(m),
+        }
+    }
 }
 
-impl<'a, 'b> ser::SerializeStruct for StructEnumSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> ser::SerializeStruct for StructEnumSerializer<'a, 'b, W> {
     type Ok = ();
     type Error = Error;
     #[inline(always)]
@@ -58,11 +73,15 @@ impl<'a, 'b> ser::SerializeStruct for StructEnumSerializer<'a, 'b> {
         self.serialize_field_strkey(key, value)
     }
     fn end(self) -> Result<()> {
-        Ok(())
+        match self.variants {
+            StructEnumSerializerVariants::Map(m) => m.end(),
+            StructEnumSerializerVariants::Struct(s) => s.end(),
+            StructEnumSerializerVariants::Enum(e) => e.end(),
+        }
     }
 }
 
-impl<'a, 'b> ser::SerializeMap for StructEnumSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> ser::SerializeMap for StructEnumSerializer<'a, 'b, W> {
     type Ok = ();
     type Error = Error;
 
@@ -70,6 +89,9 @@ impl<'a, 'b> ser::SerializeMap for StructEnumSerializer<'a, 'b> {
     where
         T: Serialize,
     {
+        if let StructEnumSerializerVariants::Map(m) = &mut self.variants {
+            return m.serialize_key(key);
+        }
         let key = string_serializer::StringSerializer::get(&key)?;
         self.curr_key = Some(key);
         Ok(())
@@ -79,6 +101,9 @@ impl<'a, 'b> ser::SerializeMap for StructEnumSerializer<'a, 'b> {
     where
         T: Serialize,
     {
+        if let StructEnumSerializerVariants::Map(m) = &mut self.variants {
+            return m.serialize_value(value);
+        }
         if let Some(s) = self.curr_key.take() {
             return self.serialize_field_strkey(&s, value);
         }
@@ -96,27 +121,166 @@ impl<'a, 'b> ser::SerializeMap for StructEnumSerializer<'a, 'b> {
         K: Serialize,
         V: Serialize,
     {
+        if let StructEnumSerializerVariants::Map(m) = &mut self.variants {
+            m.serialize_key(key)?;
+            return m.serialize_value(value);
+        }
         let key = string_serializer::StringSerializer::get(&key)?;
         self.serialize_field_strkey(&key, value)
     }
 
     fn end(self) -> Result<()> {
+        match self.variants {
+            StructEnumSerializerVariants::Map(m) => m.end(),
+            StructEnumSerializerVariants::Struct(s) => s.end(),
+            StructEnumSerializerVariants::Enum(e) => e.end(),
+        }
+    }
+}
+
+pub struct MapSerializer<'a, 'b, W = Vec<u8>> {
+    pub keytyp: &'b SmithType<usize>,
+    pub valtyp: &'b SmithType<usize>,
+    pub serializer: &'a mut Serializer<'b, W>,
+    //Length announced by serde - entries get buffered if it wasnt known upfront
+    pub len: Option<usize>,
+    pub buffer: Option<Serializer<'b>>,
+    pub entries: usize,
+}
+
+impl<'a, 'b, W: Write> MapSerializer<'a, 'b, W> {
+    pub fn new(
+        serializer: &'a mut Serializer<'b, W>,
+        keytyp: &'b SmithType<usize>,
+        valtyp: &'b SmithType<usize>,
+        len: Option<usize>,
+    ) -> Result<Self> {
+        let buffer = if let Some(len) = len {
+            serializer.current_type = &SmithType::UInt;
+            len.serialize(&mut *serializer)?;
+            None
+        } else {
+            Some(serializer.copy_context())
+        };
+        Ok(Self {
+            keytyp,
+            valtyp,
+            serializer,
+            len,
+            buffer,
+            entries: 0,
+        })
+    }
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.entries += 1;
+        let (index, keytyp) = (self.entries - 1, self.keytyp);
+        match &mut self.buffer {
+            Some(buffer) => write_key(buffer, keytyp, key).map_err(|e| entry(e, index, buffer)),
+            None => write_key(self.serializer, keytyp, key)
+                .map_err(|e| entry(e, index, self.serializer)),
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let index = self.entries.saturating_sub(1);
+        let target = match &mut self.buffer {
+            Some(buffer) => {
+                buffer.current_type = self.valtyp;
+                return value.serialize(&mut *buffer).map_err(|e| entry(e, index, buffer));
+            }
+            None => &mut *self.serializer,
+        };
+        target.current_type = self.valtyp;
+        value.serialize(&mut *target).map_err(|e| entry(e, index, target))
+    }
+
+    fn end(self) -> Result<()> {
+        if let Some(len) = self.len {
+            if len != self.entries {
+                return Err(Error::Static(
+                    "Amount of serialized map entries does not match the announced length",
+                ));
+            }
+        }
+        if let Some(buffer) = self.buffer {
+            self.serializer.current_type = &SmithType::UInt;
+            self.entries.serialize(&mut *self.serializer)?;
+            self.serializer.write_bytes(&buffer.buff)?;
+        }
         Ok(())
     }
 }
 
-pub struct EnumSerializer<'a, 'b> {
+/// Error in the map entry `index`
+fn entry<W: Write>(e: Error, index: usize, serializer: &Serializer<'_, W>) -> Error {
+    e.within(format_args!("[{index}]"), serializer.offset())
+}
+
+fn write_key<'b, W: Write, T>(
+    target: &mut Serializer<'b, W>,
+    keytyp: &'b SmithType<usize>,
+    key: &T,
+) -> Result<()>
+where
+    T: ?Sized + Serialize,
+{
+    target.current_type = keytyp;
+    if let SmithType::String = keytyp {
+        return key.serialize(target);
+    }
+    //Json only knows string keys - integer keys get parsed
+    match string_serializer::StringSerializer::get(&key) {
+        Ok(s) => {
+            if let Ok(v) = s.parse::<u64>() {
+                v.serialize(target)
+            } else {
+                s.parse::<i64>()
+                    .map_err(|_| Error::Message(format!("Map key '{s}' is not an integer")))?
+                    .serialize(target)
+            }
+        }
+        Err(_) => key.serialize(target),
+    }
+}
+
+/// Enums given as `{"tag": .., "val": ..}`. A `val` arriving before the `tag` is buffered untyped
+/// until the variant - and with it the payload type - is known
+pub struct EnumSerializer<'a, 'b, W = Vec<u8>> {
     pub smith_enum: &'b SmithEnum<usize>,
     pub enum_variant_type: Option<&'b (String, Option<SmithType<usize>>)>,
-    pub serializer: &'a mut Serializer<'b>,
+    pub serializer: &'a mut Serializer<'b, W>,
+    //`val` received before the tag
+    pub pending_val: Option<SmithValue>,
+    //Whether the payload of the variant has been written
+    pub val_written: bool,
 }
 
-impl<'a, 'b> EnumSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> EnumSerializer<'a, 'b, W> {
+    pub fn new(smith_enum: &'b SmithEnum<usize>, serializer: &'a mut Serializer<'b, W>) -> Self {
+        Self {
+            smith_enum,
+            enum_variant_type: None,
+            serializer,
+            pending_val: None,
+            val_written: false,
+        }
+    }
+
     fn serialize_enum<T: ?Sized>(&mut self, key: &str, value: &T) -> Result<()>
     where
         T: Serialize,
     {
         return if key == "tag" {
+            if self.enum_variant_type.is_some() {
+                return Err(Error::DuplicateField(key.to_owned()));
+            }
             let name = string_serializer::StringSerializer::get(&value)?;
             let variantpos = self
                 .smith_enum
@@ -130,24 +294,19 @@ impl<'a, 'b> EnumSerializer<'a, 'b> {
             variantpos.serialize(&mut *self.serializer)?;
 
             self.enum_variant_type = Some(variant);
-            Ok(())
+            match self.pending_val.take() {
+                Some(val) => self.write_val(&val),
+                None => Ok(()),
+            }
         } else if key == "val" {
-            if let Some(s) = &self.enum_variant_type {
-                if let Some(t) = &s.1 {
-                    self.serializer.current_type = t;
-                    value.serialize(&mut *self.serializer)
-                } else {
-                    Err(Error::Message(format!(
-                        "SmithEnum '{}' with variant '{}' does not have a value",
-                        &self.smith_enum.name, s.0
-                    )))
-                }
-            } else {
-                Err(Error::Message(format!(
-                    "SmithEnum '{}': variant tag not read yet",
-                    &self.smith_enum.name
-                )))
+            if self.val_written || self.pending_val.is_some() {
+                return Err(Error::DuplicateField(key.to_owned()));
+            }
+            if self.enum_variant_type.is_some() {
+                return self.write_val(value);
             }
+            self.pending_val = Some(value::to_value(value)?);
+            Ok(())
         } else {
             Err(Error::Message(format!(
                 "SmithEnum '{}' expected, only valid map keys are 'tag' and 'val' - '{}' provided",
@@ -155,33 +314,143 @@ impl<'a, 'b> EnumSerializer<'a, 'b> {
             )))
         };
     }
+
+    /// Writes the payload of the variant read from the tag
+    fn write_val<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let Some((name, payload)) = self.enum_variant_type else {
+            unreachable!("payload written before the tag");
+        };
+        let Some(t) = payload else {
+            return Err(Error::Message(format!(
+                "SmithEnum '{}' with variant '{}' does not have a value",
+                &self.smith_enum.name, name
+            )));
+        };
+        self.serializer.current_type = t;
+        value
+            .serialize(&mut *self.serializer)
+            .map_err(|e| e.within(format_args!("<{name}>"), self.serializer.offset()))?;
+        self.val_written = true;
+        Ok(())
+    }
+
+    /// Fails for a missing tag, a missing payload is handled by [MissingFields]
+    fn end(self) -> Result<()> {
+        let missing = |field: &str| Error::MissingField {
+            structure: self.smith_enum.name.clone(),
+            field: field.to_owned(),
+        };
+        let Some((_, payload)) = self.enum_variant_type else {
+            return Err(missing("tag"));
+        };
+        match payload {
+            Some(t) if !self.val_written => match self.serializer.options.missing_fields {
+                MissingFields::Default => self.serializer.write_default(t, 0),
+                MissingFields::OptionsAsNone if matches!(t, SmithType::Option(_)) => {
+                    self.serializer.write_byte(0)
+                }
+                MissingFields::Error | MissingFields::OptionsAsNone => Err(missing("val")),
+            },
+            _ => Ok(()),
+        }
+    }
 }
 
-pub struct StructSerializer<'a, 'b> {
+/// Error in the struct field `key`
+fn field<W: Write>(e: Error, key: &str, serializer: &Serializer<'_, W>) -> Error {
+    e.within(format_args!(".{key}"), serializer.offset())
+}
+
+/// Fields are matched by name. Fields arriving in schema order are written straight through,
+/// fields arriving early are buffered until all fields before them are written
+pub struct StructSerializer<'a, 'b, W = Vec<u8>> {
     pub structyp: &'b SmithStruct<usize>,
-    pub serializer: &'a mut Serializer<'b>,
+    pub serializer: &'a mut Serializer<'b, W>,
+    //Index of the next field to be written
     pub current_field_idx: usize,
+    //Encoded fields ahead of `current_field_idx`, indexed by field position
+    pub pending: Vec<Option<Serializer<'b>>>,
 }
 
-impl<'a, 'b> StructSerializer<'a, 'b> {
+impl<'a, 'b, W: Write> StructSerializer<'a, 'b, W> {
+    pub fn new(structyp: &'b SmithStruct<usize>, serializer: &'a mut Serializer<'b, W>) -> Self {
+        Self {
+            structyp,
+            serializer,
+            current_field_idx: 0,
+            pending: Vec::new(),
+        }
+    }
+
     fn serialize_field<T>(&mut self, key: &str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        let f = self
-            .structyp
-            .fields
-            .get(self.current_field_idx)
-            .ok_or(Error::GivenStructureFieldAmoutNotMatching)?;
-        if f.0 != key {
+        let fields = &self.structyp.fields;
+        if let Some((_, t)) = fields.get(self.current_field_idx).filter(|f| f.0 == key) {
+            self.serializer.current_type = t;
+            self.current_field_idx += 1;
+            value.serialize(&mut *self.serializer).map_err(|e| field(e, key, self.serializer))?;
+            return self.flush_pending();
+        }
+
+        let Some(idx) = fields.iter().position(|f| f.0 == key) else {
+            if self.serializer.options.mode == EncodeMode::Lenient {
+                log::warn!("struct '{}' has no field '{key}' - skipped", self.structyp.name);
+                return Ok(());
+            }
             return Err(Error::ExpectedStructField(format!(
-                "expected: '{}' - got '{}'",
-                f.0, key
+                "struct '{}' has no field '{key}'",
+                self.structyp.name
             )));
+        };
+        if idx < self.current_field_idx || self.pending.get(idx).is_some_and(Option::is_some) {
+            return Err(Error::DuplicateField(key.to_owned()));
         }
-        let t = &f.1;
-        self.serializer.current_type = t;
-        self.current_field_idx += 1;
-        value.serialize(&mut *self.serializer)
+        let mut buffer = self.serializer.copy_context();
+        buffer.current_type = &fields[idx].1;
+        value.serialize(&mut buffer).map_err(|e| field(e, key, &buffer))?;
+        if self.pending.len() <= idx {
+            self.pending.resize_with(idx + 1, || None);
+        }
+        self.pending[idx] = Some(buffer);
+        Ok(())
+    }
+
+    /// Writes the buffered fields directly following the last written one
+    fn flush_pending(&mut self) -> Result<()> {
+        while let Some(buffer) = self
+            .pending
+            .get_mut(self.current_field_idx)
+            .and_then(Option::take)
+        {
+            self.serializer.write_bytes(&buffer.buff)?;
+            self.current_field_idx += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining buffered fields, missing ones are handled by [MissingFields]
+    fn end(mut self) -> Result<()> {
+        let fields = &self.structyp.fields;
+        while self.current_field_idx < fields.len() {
+            let (name, t) = &fields[self.current_field_idx];
+            let missing_fields = self.serializer.options.missing_fields;
+            match missing_fields {
+                MissingFields::Default => self.serializer.write_default(t, 0)?,
+                MissingFields::OptionsAsNone if matches!(t, SmithType::Option(_)) => {
+                    self.serializer.write_byte(0)?
+                }
+                MissingFields::Error | MissingFields::OptionsAsNone => {
+                    return Err(Error::MissingField {
+                        structure: self.structyp.name.clone(),
+                        field: name.clone(),
+                    })
+                }
+            }
+            self.current_field_idx += 1;
+            self.flush_pending()?;
+        }
+        Ok(())
     }
 }
@@ -11,7 +11,7 @@ pub struct StringSerializer {
 
 impl UnwindSafe for StringSerializer {}
 impl StringSerializer {
-    pub fn get<T: Serialize>(t: &T) -> Result<String> {
+    pub fn get<T: ?Sized + Serialize>(t: &T) -> Result<String> {
         let mut s = Self { val: None };
 
         t.serialize(&mut s)?;
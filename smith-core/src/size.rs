@@ -0,0 +1,167 @@
+//! Static size analysis of schema types - how many bytes an encoded value can take.
+//!
+//! The bounds describe the message body, the optional format header
+//! ([crate::WireFormat::header]) is not included.
+//! Use [crate::Smith::encoded_len] for the exact size of a specific value.
+use crate::generics_engine::{SmithCustomTyp, SmithCustomTypVariant};
+use crate::wellknown::NANOS_PER_SEC;
+use crate::SmithType;
+
+/// Largest udInt / dInt
+const DYNUM_MAX_LEN: usize = 8;
+
+/// Inclusive range of encoded sizes in bytes. `max` is `None` for unbounded types
+/// (strings, arrays, maps and recursive types)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBounds {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl SizeBounds {
+    pub const fn exact(size: usize) -> Self {
+        Self {
+            min: size,
+            max: Some(size),
+        }
+    }
+
+    const fn unbounded(min: usize) -> Self {
+        Self { min, max: None }
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.max == Some(self.min)
+    }
+
+    /// Bounds of `self` followed by `other`
+    fn then(self, other: Self) -> Self {
+        Self {
+            min: self.min.saturating_add(other.min),
+            max: self.max.zip(other.max).and_then(|(a, b)| a.checked_add(b)),
+        }
+    }
+
+    /// Bounds of a value that is either `self` or `other`
+    fn or(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.zip(other.max).map(|(a, b)| a.max(b)),
+        }
+    }
+
+    fn repeat(self, n: usize) -> Self {
+        Self {
+            min: self.min.saturating_mul(n),
+            max: self.max.and_then(|m| m.checked_mul(n)),
+        }
+    }
+}
+
+fn udint_len(v: u64) -> usize {
+    Dynum::encoded_len(v).map_or(DYNUM_MAX_LEN, |len| len as usize)
+}
+
+/// Bounds of any udInt / dInt
+const DYNUM: SizeBounds = SizeBounds {
+    min: 1,
+    max: Some(DYNUM_MAX_LEN),
+};
+
+/// Size bounds of `typ`, custom types are looked up in `prog`
+pub fn size_bounds(typ: &SmithType<usize>, prog: &[SmithCustomTyp<usize>]) -> SizeBounds {
+    bounds_of(typ, prog, &mut Vec::new())
+}
+
+fn bounds_of<'a>(
+    typ: &SmithType<usize>,
+    prog: &'a [SmithCustomTyp<usize>],
+    visiting: &mut Vec<&'a SmithCustomTyp<usize>>,
+) -> SizeBounds {
+    let nanos = SizeBounds {
+        min: 1,
+        max: Some(udint_len(NANOS_PER_SEC as u64 - 1)),
+    };
+    match typ {
+        SmithType::I8 | SmithType::U8 | SmithType::Bool => SizeBounds::exact(1),
+        SmithType::I16 | SmithType::U16 => SizeBounds::exact(2),
+        SmithType::I32 | SmithType::U32 | SmithType::F32 => SizeBounds::exact(4),
+        SmithType::U64 | SmithType::F64 => SizeBounds::exact(8),
+        SmithType::UInt | SmithType::Int => DYNUM,
+        //Terminator or length prefix
+        SmithType::String | SmithType::Bytes => SizeBounds::unbounded(1),
+        SmithType::Array(_) | SmithType::Map(_, _) => SizeBounds::unbounded(1),
+
+        SmithType::Timestamp | SmithType::Duration => DYNUM.then(nanos),
+        SmithType::Uuid => SizeBounds::exact(16),
+        SmithType::Decimal => DYNUM.then(SizeBounds::exact(1)),
+
+        SmithType::FixedArray(elem, len) => bounds_of(elem, prog, visiting).repeat(*len),
+        //Presence byte
+        SmithType::Option(inner) => {
+            SizeBounds::exact(1).or(SizeBounds::exact(1).then(bounds_of(inner, prog, visiting)))
+        }
+        SmithType::CustomType(id, _) => match prog.get(*id) {
+            Some(custom) => custom_bounds(custom, prog, visiting),
+            None => SizeBounds::unbounded(0),
+        },
+    }
+}
+
+fn custom_bounds<'a>(
+    custom: &'a SmithCustomTyp<usize>,
+    prog: &'a [SmithCustomTyp<usize>],
+    visiting: &mut Vec<&'a SmithCustomTyp<usize>>,
+) -> SizeBounds {
+    //Recursive types can nest without limit, the surrounding Option / enum provides the minimum
+    if visiting.iter().any(|v| std::ptr::eq(*v, custom)) {
+        return SizeBounds::unbounded(0);
+    }
+    visiting.push(custom);
+    let bounds = match &custom.variant {
+        SmithCustomTypVariant::Struct(s) => s
+            .fields
+            .iter()
+            .fold(SizeBounds::exact(0), |acc, (_, t)| {
+                acc.then(bounds_of(t, prog, visiting))
+            }),
+        SmithCustomTypVariant::Enum(e) => e
+            .variants
+            .iter()
+            .enumerate()
+            .map(|(i, (_, payload))| {
+                let tag = SizeBounds::exact(udint_len(i as u64));
+                match payload {
+                    Some(t) => tag.then(bounds_of(t, prog, visiting)),
+                    None => tag,
+                }
+            })
+            .reduce(SizeBounds::or)
+            .unwrap_or(SizeBounds::exact(0)),
+        SmithCustomTypVariant::Flags(f) => SizeBounds::exact(f.byte_len()),
+    };
+    visiting.pop();
+    bounds
+}
+
+impl SmithCustomTyp<usize> {
+    /// Size bounds of this type, `prog` is the program it belongs to
+    pub fn size_bounds(&self, prog: &[SmithCustomTyp<usize>]) -> SizeBounds {
+        custom_bounds(self, prog, &mut Vec::new())
+    }
+
+    /// Smallest possible encoding in bytes - a lower bound for recursive types
+    pub fn min_size(&self, prog: &[SmithCustomTyp<usize>]) -> usize {
+        self.size_bounds(prog).min
+    }
+
+    /// Largest possible encoding in bytes, `None` if the size is unbounded
+    pub fn max_size(&self, prog: &[SmithCustomTyp<usize>]) -> Option<usize> {
+        self.size_bounds(prog).max
+    }
+
+    /// Every value of this type encodes to the same amount of bytes
+    pub fn is_fixed_size(&self, prog: &[SmithCustomTyp<usize>]) -> bool {
+        self.size_bounds(prog).is_fixed()
+    }
+}
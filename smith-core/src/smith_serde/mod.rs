@@ -4,7 +4,8 @@ use std::fmt::{self, Display};
 use Dynum::DynumError;
 use serde::{de, ser};
 
-use crate::SmithType;
+use crate::generics_engine::SmithCustomTyp;
+use crate::{SmithType, WireFormat};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -17,9 +18,22 @@ pub enum Error {
     Eof,
     TrailingCharacters,
     UTF8Error(std::str::Utf8Error),
+    Io(std::io::Error),
+    InvalidBase64(base64::DecodeError),
+    InvalidWellKnown {
+        typ: &'static str,
+        value: String,
+    },
     Expected(SmithType<usize>),
     UDIntNotCastInto(SmithType<usize>),
-    EndOfStream,
+    /// The data ended inside the message - at least `needed` more bytes are required
+    EndOfStream {
+        needed: usize,
+    },
+    WireFormatMismatch {
+        expected: WireFormat,
+        received: Option<WireFormat>,
+    },
 
     StrToCharError(&'static str),
     StringSerializerTypeNotString,
@@ -27,13 +41,35 @@ pub enum Error {
     Static(&'static str),
     CustomTypeNotFoundById,
     ExpectedArrayLen,
+    FixedArrayLenMismatch {
+        expected: usize,
+        received: usize,
+    },
     DynamicNumberError(String),
     I64CastI32Failed,
     ValueNotHavingField(String),
     GivenStructureFieldAmoutNotMatching,
     ExpectedStructField(String),
+    MissingField {
+        structure: String,
+        field: String,
+    },
+    DuplicateField(String),
     UnknownStruct(String),
     EnumVariantNotFound(String),
+    /// The data names a variant the enum does not have
+    EnumVariantOutOfRange {
+        enumeration: String,
+        index: u64,
+    },
+    /// A [crate::DecodeLimits] bound was exceeded
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+    },
+    UnknownFlag(String),
+    /// A bool or option presence byte other than `0` and `1`
+    InvalidBool(u8),
     MissmatchedType {
         expected: String,
         received: &'static str,
@@ -48,6 +84,68 @@ pub enum Error {
         expected: String,
         received: String,
     },
+
+    /// `source` occurred at byte `offset` of the message, inside the value at `path`
+    /// (e.g. `Packet.payload<Order>.items[3].amount`: field, variant and element of the root type)
+    Context {
+        offset: usize,
+        path: String,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Adds `segment` in front of the path of the error, errors without context get one at `offset`
+    pub(crate) fn within(self, segment: impl Display, offset: usize) -> Self {
+        match self {
+            Error::Context {
+                offset,
+                path,
+                source,
+            } => Error::Context {
+                offset,
+                path: format!("{segment}{path}"),
+                source,
+            },
+            source => Error::Context {
+                offset,
+                path: segment.to_string(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// The error without [Error::Context]
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root_cause(),
+            e => e,
+        }
+    }
+
+    /// Byte offset in the message the error occurred at, if known
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            Error::Context { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Path of the value the error occurred in, if known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::Context { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// First path segment for values of `typ` - the name of custom types, empty for others
+pub(crate) fn root_name<'a>(typ: &SmithType<usize>, prog: &'a [SmithCustomTyp<usize>]) -> &'a str {
+    match typ {
+        SmithType::CustomType(id, _) => prog.get(*id).map_or("", |t| t.get_name()),
+        _ => "",
+    }
 }
 
 impl ser::Error for Error {
@@ -63,12 +161,89 @@ impl de::Error for Error {
 }
 
 impl Display for Error {
-    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::SerdeMessage(msg) => formatter.write_str(msg),
-            _ => formatter.write_str(format!("{:?}", self).as_str()),
+            Error::TryFromIntError => f.write_str("number does not fit into the target type"),
+            Error::DynumError(e) => write!(f, "invalid dynamic number: {e}"),
+            Error::Message(msg) | Error::SerdeMessage(msg) => f.write_str(msg),
+            Error::Eof => f.write_str("unexpected end of input"),
+            Error::TrailingCharacters => f.write_str("trailing bytes after the message"),
+            Error::UTF8Error(e) => write!(f, "invalid UTF-8: {e}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::InvalidBase64(e) => write!(f, "invalid base64: {e}"),
+            Error::InvalidWellKnown { typ, value } => write!(f, "invalid {typ} '{value}'"),
+            Error::Expected(typ) => write!(f, "expected {typ:?}"),
+            Error::UDIntNotCastInto(typ) => write!(f, "udInt does not fit into {typ:?}"),
+            Error::EndOfStream { needed } => {
+                write!(f, "data ends inside the message, {needed} more byte(s) needed")
+            }
+            Error::WireFormatMismatch { expected, received } => match received {
+                Some(received) => write!(
+                    f,
+                    "wire format mismatch: expected {expected:?}, received {received:?}"
+                ),
+                None => write!(f, "wire format mismatch: expected {expected:?}, no header found"),
+            },
+            Error::StrToCharError(msg) => write!(f, "string is not a char: {msg}"),
+            Error::StringSerializerTypeNotString => f.write_str("expected a string"),
+            Error::Static(msg) => f.write_str(msg),
+            Error::CustomTypeNotFoundById => f.write_str("type not found in the schema"),
+            Error::ExpectedArrayLen => f.write_str("sequence length has to be known upfront"),
+            Error::FixedArrayLenMismatch { expected, received } => write!(
+                f,
+                "fixed array of {expected} elements, received {received}"
+            ),
+            Error::DynamicNumberError(msg) => write!(f, "invalid dynamic number: {msg}"),
+            Error::I64CastI32Failed => f.write_str("i64 does not fit into i32"),
+            Error::ValueNotHavingField(field) => write!(f, "value has no field '{field}'"),
+            Error::GivenStructureFieldAmoutNotMatching => {
+                f.write_str("number of fields does not match the schema")
+            }
+            Error::ExpectedStructField(msg) => write!(f, "unexpected field: {msg}"),
+            Error::MissingField { structure, field } => {
+                write!(f, "struct '{structure}' is missing field '{field}'")
+            }
+            Error::DuplicateField(field) => write!(f, "field '{field}' given more than once"),
+            Error::UnknownStruct(name) => write!(f, "unknown struct '{name}'"),
+            Error::EnumVariantNotFound(name) => write!(f, "unknown enum variant '{name}'"),
+            Error::EnumVariantOutOfRange { enumeration, index } => {
+                write!(f, "enum '{enumeration}' has no variant {index}")
+            }
+            Error::LimitExceeded { limit, max } => write!(f, "{limit} exceeds the limit of {max}"),
+            Error::UnknownFlag(flag) => write!(f, "unknown flag {flag}"),
+            Error::InvalidBool(b) => write!(f, "expected 0 or 1, found {b:#04x}"),
+            Error::MissmatchedType { expected, received } => {
+                write!(f, "expected {expected}, received {received}")
+            }
+            Error::MissmatchedType2 { expected, received } => {
+                write!(f, "expected {expected}, received {received:?}")
+            }
+            Error::EnumVariantWrongName { expected, received } => {
+                write!(f, "enum variant '{received}' does not match '{expected}'")
+            }
+            Error::Context {
+                offset,
+                path,
+                source,
+            } => {
+                if path.is_empty() {
+                    write!(f, "byte {offset}: {source}")
+                } else {
+                    write!(f, "{path} (byte {offset}): {source}")
+                }
+            }
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Context { source, .. } => Some(source.as_ref()),
+            Error::UTF8Error(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::InvalidBase64(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,848 @@
+//! Untyped messages - [SmithValue] holds any value of a schema type without a rust type for it.
+//!
+//! [crate::Smith::decode_value] reads values guided by the schema, so every number keeps its
+//! schema type and enums come with their variant name.
+//! Serializing a value produces the json layout of `binary2json`, any serializer works -
+//! bytes are base64 strings for human-readable formats and raw bytes for all others.
+//! Deserializing from self-describing formats (e.g. json) is untyped: objects become
+//! [SmithValue::Struct], integers [SmithValue::Int] / [SmithValue::UInt], encoding them
+//! with [crate::Smith::encode_value] converts them to the schema types.
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{
+    Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::generics_engine::SmithCustomTypVariant;
+use crate::resolver::ResolvedSmithProgram;
+use crate::smith_serde::Error;
+use crate::{wellknown, SmithType};
+
+/// Value of any schema type, the variants mirror [SmithType]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmithValue {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    /// udInt
+    UInt(u64),
+    /// dInt
+    Int(i64),
+    String(String),
+    Bytes(Vec<u8>),
+
+    Timestamp {
+        secs: i64,
+        nanos: u32,
+    },
+    Duration {
+        secs: u64,
+        nanos: u32,
+    },
+    Uuid([u8; 16]),
+    Decimal {
+        mantissa: i64,
+        scale: u8,
+    },
+
+    /// `Array` and fixed arrays
+    Array(Vec<SmithValue>),
+    Map(Vec<(SmithValue, SmithValue)>),
+    Option(Option<Box<SmithValue>>),
+    /// Fields in schema order
+    Struct(Vec<(String, SmithValue)>),
+    Enum {
+        variant: String,
+        value: Option<Box<SmithValue>>,
+    },
+    /// Names of the set flags
+    Flags(Vec<String>),
+}
+
+/// Returned by indexing for missing fields and elements
+const NONE: SmithValue = SmithValue::Option(None);
+
+impl SmithValue {
+    /// Field `name` of a struct
+    pub fn get(&self, name: &str) -> Option<&SmithValue> {
+        match self {
+            SmithValue::Struct(fields) => fields.iter().find(|f| f.0 == name).map(|f| &f.1),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut SmithValue> {
+        match self {
+            SmithValue::Struct(fields) => fields.iter_mut().find(|f| f.0 == name).map(|f| &mut f.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            SmithValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Any integer that fits into u64
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            SmithValue::U8(v) => Some(v.into()),
+            SmithValue::U16(v) => Some(v.into()),
+            SmithValue::U32(v) => Some(v.into()),
+            SmithValue::U64(v) | SmithValue::UInt(v) => Some(v),
+            _ => self.as_signed().and_then(|v| u64::try_from(v).ok()),
+        }
+    }
+
+    /// Any integer that fits into i64
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_signed()
+            .or_else(|| self.as_u64().and_then(|v| i64::try_from(v).ok()))
+    }
+
+    fn as_signed(&self) -> Option<i64> {
+        match *self {
+            SmithValue::I8(v) => Some(v.into()),
+            SmithValue::I16(v) => Some(v.into()),
+            SmithValue::I32(v) => Some(v.into()),
+            SmithValue::Int(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            SmithValue::F32(v) => Some(v.into()),
+            SmithValue::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            SmithValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            SmithValue::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[SmithValue]> {
+        match self {
+            SmithValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Variant name and payload of an enum
+    pub fn as_variant(&self) -> Option<(&str, Option<&SmithValue>)> {
+        match self {
+            SmithValue::Enum { variant, value } => Some((variant, value.as_deref())),
+            _ => None,
+        }
+    }
+}
+
+/// Struct fields by name - missing fields are `Option(None)`
+impl Index<&str> for SmithValue {
+    type Output = SmithValue;
+
+    fn index(&self, name: &str) -> &SmithValue {
+        self.get(name).unwrap_or(&NONE)
+    }
+}
+
+/// Panics if the field does not exist
+impl IndexMut<&str> for SmithValue {
+    fn index_mut(&mut self, name: &str) -> &mut SmithValue {
+        self.get_mut(name)
+            .unwrap_or_else(|| panic!("no field '{name}'"))
+    }
+}
+
+/// Array elements - missing elements are `Option(None)`
+impl Index<usize> for SmithValue {
+    type Output = SmithValue;
+
+    fn index(&self, index: usize) -> &SmithValue {
+        self.as_array().and_then(|a| a.get(index)).unwrap_or(&NONE)
+    }
+}
+
+/// Same layout as `binary2json`
+impl Serialize for SmithValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let formatted = match self {
+            SmithValue::Timestamp { secs, nanos } => wellknown::format_timestamp(*secs, *nanos),
+            SmithValue::Duration { secs, nanos } => wellknown::format_duration(*secs, *nanos),
+            _ => Ok(String::new()),
+        };
+        match self {
+            SmithValue::Bool(v) => serializer.serialize_bool(*v),
+            SmithValue::I8(v) => serializer.serialize_i8(*v),
+            SmithValue::I16(v) => serializer.serialize_i16(*v),
+            SmithValue::I32(v) => serializer.serialize_i32(*v),
+            SmithValue::U8(v) => serializer.serialize_u8(*v),
+            SmithValue::U16(v) => serializer.serialize_u16(*v),
+            SmithValue::U32(v) => serializer.serialize_u32(*v),
+            SmithValue::U64(v) | SmithValue::UInt(v) => serializer.serialize_u64(*v),
+            SmithValue::F32(v) => serializer.serialize_f32(*v),
+            SmithValue::F64(v) => serializer.serialize_f64(*v),
+            SmithValue::Int(v) => serializer.serialize_i64(*v),
+            SmithValue::String(v) => serializer.serialize_str(v),
+            //Base64 strings like `binary2json`, raw bytes for binary formats
+            SmithValue::Bytes(v) if serializer.is_human_readable() => {
+                serializer.serialize_str(&BASE64.encode(v))
+            }
+            SmithValue::Bytes(v) => serializer.serialize_bytes(v),
+            SmithValue::Timestamp { .. } | SmithValue::Duration { .. } => {
+                serializer.serialize_str(&formatted.map_err(serde::ser::Error::custom)?)
+            }
+            SmithValue::Uuid(v) => serializer.serialize_str(&wellknown::format_uuid(v)),
+            SmithValue::Decimal { mantissa, scale } => {
+                serializer.serialize_str(&wellknown::format_decimal(*mantissa, *scale))
+            }
+            SmithValue::Array(v) => serializer.collect_seq(v),
+            SmithValue::Map(v) => serializer.collect_map(v.iter().map(|(k, v)| (k, v))),
+            SmithValue::Option(None) => serializer.serialize_none(),
+            SmithValue::Option(Some(v)) => serializer.serialize_some(v),
+            SmithValue::Struct(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+            SmithValue::Enum { variant, value } => {
+                let mut map = serializer.serialize_map(Some(1 + value.is_some() as usize))?;
+                map.serialize_entry("tag", variant)?;
+                if let Some(value) = value {
+                    map.serialize_entry("val", value)?;
+                }
+                map.end()
+            }
+            SmithValue::Flags(names) => {
+                let mut seq = serializer.serialize_seq(Some(names.len()))?;
+                for name in names {
+                    seq.serialize_element(name)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+/// Buffers any serde value as untyped [SmithValue] without losing numbers, bytes or map keys -
+/// serializing the result to the smith serializer writes the same bytes as the original value
+pub(crate) fn to_value<T: ?Sized + Serialize>(value: &T) -> Result<SmithValue, Error> {
+    value.serialize(ValueSerializer)
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = SmithValue;
+    type Error = Error;
+    type SerializeSeq = SeqValue;
+    type SerializeTuple = SeqValue;
+    type SerializeTupleStruct = SeqValue;
+    type SerializeTupleVariant = Impossible<SmithValue, Error>;
+    type SerializeMap = MapValue;
+    type SerializeStruct = StructValue;
+    type SerializeStructVariant = StructValue;
+
+    fn serialize_bool(self, v: bool) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<SmithValue, Error> {
+        Ok(SmithValue::I8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<SmithValue, Error> {
+        Ok(SmithValue::I16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<SmithValue, Error> {
+        Ok(SmithValue::I32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<SmithValue, Error> {
+        Ok(SmithValue::U8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<SmithValue, Error> {
+        Ok(SmithValue::U16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<SmithValue, Error> {
+        Ok(SmithValue::U32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<SmithValue, Error> {
+        Ok(SmithValue::U64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<SmithValue, Error> {
+        Ok(SmithValue::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<SmithValue, Error> {
+        Ok(SmithValue::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<SmithValue, Error> {
+        Ok(SmithValue::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<SmithValue, Error> {
+        Ok(SmithValue::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Bytes(v.to_owned()))
+    }
+
+    fn serialize_none(self) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Option(Some(Box::new(to_value(value)?))))
+    }
+
+    //The smith serializer treats unit like none - empty structs and absent options
+    fn serialize_unit(self) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Enum {
+            variant: variant.to_owned(),
+            value: None,
+        })
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<SmithValue, Error> {
+        to_value(value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Enum {
+            variant: variant.to_owned(),
+            value: Some(Box::new(to_value(value)?)),
+        })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqValue, Error> {
+        Ok(SeqValue(Vec::with_capacity(len.unwrap_or(0).min(4096))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqValue, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqValue, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Static("Smith does not support enum tuple variants"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapValue, Error> {
+        Ok(MapValue {
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructValue, Error> {
+        Ok(StructValue {
+            variant: None,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructValue, Error> {
+        Ok(StructValue {
+            variant: Some(variant),
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+struct SeqValue(Vec<SmithValue>);
+
+impl SerializeSeq for SeqValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Array(self.0))
+    }
+}
+
+impl SerializeTuple for SeqValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleStruct for SeqValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+/// Keys keep their type, e.g. integer keys of `Map<u32, _>`
+struct MapValue {
+    entries: Vec<(SmithValue, SmithValue)>,
+    key: Option<SmithValue>,
+}
+
+impl SerializeMap for MapValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or(Error::Static("map value without a key"))?;
+        self.entries.push((key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        Ok(SmithValue::Map(self.entries))
+    }
+}
+
+struct StructValue {
+    variant: Option<&'static str>,
+    fields: Vec<(String, SmithValue)>,
+}
+
+impl SerializeStruct for StructValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.fields.push((key.to_owned(), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        let value = SmithValue::Struct(self.fields);
+        Ok(match self.variant {
+            Some(variant) => SmithValue::Enum {
+                variant: variant.to_owned(),
+                value: Some(Box::new(value)),
+            },
+            None => value,
+        })
+    }
+}
+
+impl SerializeStructVariant for StructValue {
+    type Ok = SmithValue;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<SmithValue, Error> {
+        SerializeStruct::end(self)
+    }
+}
+
+/// Untyped, for self-describing formats - see the module documentation
+impl<'de> Deserialize<'de> for SmithValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UntypedVisitor)
+    }
+}
+
+struct UntypedVisitor;
+
+impl<'de> Visitor<'de> for UntypedVisitor {
+    type Value = SmithValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<SmithValue, E> {
+        Ok(SmithValue::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<SmithValue, E> {
+        Ok(SmithValue::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<SmithValue, E> {
+        Ok(SmithValue::UInt(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<SmithValue, E> {
+        Ok(SmithValue::F64(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<SmithValue, E> {
+        Ok(SmithValue::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<SmithValue, E> {
+        Ok(SmithValue::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<SmithValue, E> {
+        Ok(SmithValue::Bytes(v.to_owned()))
+    }
+
+    fn visit_none<E>(self) -> Result<SmithValue, E> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<SmithValue, E> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<SmithValue, D::Error> {
+        Ok(SmithValue::Option(Some(Box::new(SmithValue::deserialize(
+            deserializer,
+        )?))))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SmithValue, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(SmithValue::Array(values))
+    }
+
+    /// Objects with string keys become structs, others maps
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SmithValue, A::Error> {
+        let mut entries: Vec<(SmithValue, SmithValue)> = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        if !entries
+            .iter()
+            .all(|(k, _)| matches!(k, SmithValue::String(_)))
+        {
+            return Ok(SmithValue::Map(entries));
+        }
+        let fields = entries.into_iter().map(|(k, v)| match k {
+            SmithValue::String(name) => (name, v),
+            _ => unreachable!(),
+        });
+        Ok(SmithValue::Struct(fields.collect()))
+    }
+}
+
+/// Reads a value of `typ` from the smith deserializer, see [crate::Smith::decode_value]
+pub(crate) struct ValueSeed<'a> {
+    pub typ: &'a SmithType<usize>,
+    pub prog: &'a ResolvedSmithProgram,
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed<'_> {
+    type Value = SmithValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<SmithValue, D::Error> {
+        let visitor = TypedVisitor(self);
+        match visitor.0.typ {
+            SmithType::Bool => deserializer.deserialize_bool(visitor),
+            SmithType::String => deserializer.deserialize_str(visitor),
+            SmithType::Bytes | SmithType::Uuid => deserializer.deserialize_bytes(visitor),
+            SmithType::Timestamp | SmithType::Duration | SmithType::Decimal => {
+                deserializer.deserialize_map(visitor)
+            }
+            SmithType::Array(_) | SmithType::FixedArray(..) => {
+                deserializer.deserialize_seq(visitor)
+            }
+            SmithType::Map(..) => deserializer.deserialize_map(visitor),
+            SmithType::Option(_) => deserializer.deserialize_option(visitor),
+            SmithType::CustomType(id, _) => match visitor.0.prog.get(*id).map(|t| &t.variant) {
+                Some(SmithCustomTypVariant::Struct(_)) => deserializer.deserialize_map(visitor),
+                Some(SmithCustomTypVariant::Enum(_)) => {
+                    deserializer.deserialize_enum("", &[], visitor)
+                }
+                _ => deserializer.deserialize_any(visitor),
+            },
+            _ => deserializer.deserialize_any(visitor),
+        }
+    }
+}
+
+/// Builds the [SmithValue] variant of the schema type
+struct TypedVisitor<'a>(ValueSeed<'a>);
+
+impl<'a> TypedVisitor<'a> {
+    fn seed(&self, typ: &'a SmithType<usize>) -> ValueSeed<'a> {
+        ValueSeed {
+            typ,
+            prog: self.0.prog,
+        }
+    }
+
+    fn custom(&self) -> Option<&'a SmithCustomTypVariant<usize>> {
+        match self.0.typ {
+            SmithType::CustomType(id, _) => self.0.prog.get(*id).map(|t| &t.variant),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for TypedVisitor<'_> {
+    type Value = SmithValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value of {:?}", self.0.typ)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<SmithValue, E> {
+        Ok(SmithValue::Bool(v))
+    }
+
+    fn visit_i8<E>(self, v: i8) -> Result<SmithValue, E> {
+        Ok(SmithValue::I8(v))
+    }
+
+    fn visit_i16<E>(self, v: i16) -> Result<SmithValue, E> {
+        Ok(SmithValue::I16(v))
+    }
+
+    fn visit_i32<E>(self, v: i32) -> Result<SmithValue, E> {
+        Ok(SmithValue::I32(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<SmithValue, E> {
+        Ok(SmithValue::Int(v))
+    }
+
+    fn visit_u8<E>(self, v: u8) -> Result<SmithValue, E> {
+        Ok(SmithValue::U8(v))
+    }
+
+    fn visit_u16<E>(self, v: u16) -> Result<SmithValue, E> {
+        Ok(SmithValue::U16(v))
+    }
+
+    fn visit_u32<E>(self, v: u32) -> Result<SmithValue, E> {
+        Ok(SmithValue::U32(v))
+    }
+
+    /// u64, udInt or the mask of flags
+    fn visit_u64<E>(self, v: u64) -> Result<SmithValue, E> {
+        Ok(match (self.0.typ, self.custom()) {
+            (SmithType::U64, _) => SmithValue::U64(v),
+            (_, Some(SmithCustomTypVariant::Flags(f))) => {
+                SmithValue::Flags(f.names(v).map(str::to_owned).collect())
+            }
+            _ => SmithValue::UInt(v),
+        })
+    }
+
+    fn visit_f32<E>(self, v: f32) -> Result<SmithValue, E> {
+        Ok(SmithValue::F32(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<SmithValue, E> {
+        Ok(SmithValue::F64(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<SmithValue, E> {
+        Ok(SmithValue::String(v.to_owned()))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<SmithValue, E> {
+        match self.0.typ {
+            SmithType::Uuid => v
+                .try_into()
+                .map(SmithValue::Uuid)
+                .map_err(|_| E::invalid_length(v.len(), &self)),
+            _ => Ok(SmithValue::Bytes(v.to_owned())),
+        }
+    }
+
+    fn visit_none<E>(self) -> Result<SmithValue, E> {
+        Ok(SmithValue::Option(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<SmithValue, D::Error> {
+        let SmithType::Option(inner) = self.0.typ else {
+            return Err(de::Error::invalid_type(de::Unexpected::Option, &self));
+        };
+        let value = self.seed(inner).deserialize(deserializer)?;
+        Ok(SmithValue::Option(Some(Box::new(value))))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SmithValue, A::Error> {
+        let (SmithType::Array(elemtyp) | SmithType::FixedArray(elemtyp, _)) = self.0.typ else {
+            return Err(de::Error::invalid_type(de::Unexpected::Seq, &self));
+        };
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(value) = seq.next_element_seed(self.seed(elemtyp))? {
+            values.push(value);
+        }
+        Ok(SmithValue::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SmithValue, A::Error> {
+        if let SmithType::Map(keytyp, valtyp) = self.0.typ {
+            let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(4096));
+            while let Some(key) = map.next_key_seed(self.seed(keytyp))? {
+                entries.push((key, map.next_value_seed(self.seed(valtyp))?));
+            }
+            return Ok(SmithValue::Map(entries));
+        }
+        let structyp = match (wellknown::struct_of(self.0.typ), self.custom()) {
+            (Some(s), _) | (None, Some(SmithCustomTypVariant::Struct(s))) => s,
+            _ => return Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+        };
+        let mut fields = Vec::with_capacity(structyp.fields.len());
+        while let Some(name) = map.next_key::<String>()? {
+            let typ = structyp
+                .fields
+                .iter()
+                .find(|f| f.0 == name)
+                .map(|f| &f.1)
+                .ok_or_else(|| de::Error::unknown_field(&name, &[]))?;
+            fields.push((name, map.next_value_seed(self.seed(typ))?));
+        }
+        let number = |i: usize| fields.get(i).map(|f: &(String, SmithValue)| &f.1);
+        let nanos = || {
+            number(1)
+                .and_then(SmithValue::as_u64)
+                .and_then(|n| u32::try_from(n).ok())
+        };
+        let invalid = || de::Error::invalid_value(de::Unexpected::Map, &self);
+        Ok(match self.0.typ {
+            SmithType::Timestamp => SmithValue::Timestamp {
+                secs: number(0).and_then(SmithValue::as_i64).ok_or_else(invalid)?,
+                nanos: nanos().ok_or_else(invalid)?,
+            },
+            SmithType::Duration => SmithValue::Duration {
+                secs: number(0).and_then(SmithValue::as_u64).ok_or_else(invalid)?,
+                nanos: nanos().ok_or_else(invalid)?,
+            },
+            SmithType::Decimal => SmithValue::Decimal {
+                mantissa: number(0).and_then(SmithValue::as_i64).ok_or_else(invalid)?,
+                scale: match number(1) {
+                    Some(SmithValue::U8(scale)) => *scale,
+                    _ => return Err(invalid()),
+                },
+            },
+            _ => SmithValue::Struct(fields),
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<SmithValue, A::Error> {
+        let Some(SmithCustomTypVariant::Enum(e)) = self.custom() else {
+            return Err(de::Error::invalid_type(de::Unexpected::Enum, &self));
+        };
+        let (variant, access) = data.variant::<String>()?;
+        let payload = e
+            .variants
+            .iter()
+            .find(|v| v.0 == variant)
+            .ok_or_else(|| de::Error::unknown_variant(&variant, &[]))?;
+        let value = match &payload.1 {
+            Some(typ) => Some(Box::new(access.newtype_variant_seed(self.seed(typ))?)),
+            None => {
+                access.unit_variant()?;
+                None
+            }
+        };
+        Ok(SmithValue::Enum { variant, value })
+    }
+}
@@ -0,0 +1,229 @@
+//! Well-known schema types and their canonical string representation (used in json).
+//!
+//! | type        | binary layout                             | string                       |
+//! |-------------|-------------------------------------------|------------------------------|
+//! | `timestamp` | dInt secs since unix epoch + udInt nanos  | RFC 3339 `2023-04-01T12:00Z` |
+//! | `duration`  | udInt secs + udInt nanos                  | ISO-8601 `PT90.5S`           |
+//! | `uuid`      | 16 raw bytes                              | `67e55044-10b1-426f-...`     |
+//! | `decimal`   | dInt mantissa + u8 scale (m * 10^-scale)  | `-12.50`                     |
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::generics_engine::SmithStruct;
+use crate::smith_serde::{Error, Result};
+use crate::SmithType;
+
+pub const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+fn invalid(typ: &'static str, value: &str) -> Error {
+    Error::InvalidWellKnown {
+        typ,
+        value: value.to_owned(),
+    }
+}
+
+/// Struct layout of `timestamp`, `duration` and `decimal` - used by rust types that aren't
+/// strings, e.g. `std::time::Duration` is (de)serialized as struct `{secs, nanos}`
+pub(crate) fn struct_of(typ: &SmithType<usize>) -> Option<&'static SmithStruct<usize>> {
+    static TIMESTAMP: OnceLock<SmithStruct<usize>> = OnceLock::new();
+    static DURATION: OnceLock<SmithStruct<usize>> = OnceLock::new();
+    static DECIMAL: OnceLock<SmithStruct<usize>> = OnceLock::new();
+    let layout = |name: &str, fields: [(&str, SmithType<usize>); 2]| SmithStruct {
+        name: name.to_owned(),
+        fields: fields.map(|(n, t)| (n.to_owned(), t)).into(),
+    };
+    match typ {
+        SmithType::Timestamp => Some(TIMESTAMP.get_or_init(|| {
+            layout("timestamp", [("secs", SmithType::Int), ("nanos", SmithType::UInt)])
+        })),
+        SmithType::Duration => Some(DURATION.get_or_init(|| {
+            layout("duration", [("secs", SmithType::UInt), ("nanos", SmithType::UInt)])
+        })),
+        SmithType::Decimal => Some(DECIMAL.get_or_init(|| {
+            layout("decimal", [("mantissa", SmithType::Int), ("scale", SmithType::U8)])
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "timestamp")]
+pub fn parse_timestamp(s: &str) -> Result<(i64, u32)> {
+    let t = chrono::DateTime::parse_from_rfc3339(s).map_err(|_| invalid("timestamp", s))?;
+    //Leap seconds are reported as nanos >= 1s by chrono and can't be represented
+    let nanos = t.timestamp_subsec_nanos();
+    if nanos >= NANOS_PER_SEC {
+        return Err(invalid("timestamp", s));
+    }
+    Ok((t.timestamp(), nanos))
+}
+
+#[cfg(feature = "timestamp")]
+pub fn format_timestamp(secs: i64, nanos: u32) -> Result<String> {
+    chrono::DateTime::from_timestamp(secs, nanos)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+        .ok_or_else(|| invalid("timestamp", &format!("{secs}s {nanos}ns")))
+}
+
+#[cfg(not(feature = "timestamp"))]
+pub fn parse_timestamp(_s: &str) -> Result<(i64, u32)> {
+    Err(Error::Static("smith-core was built without the 'timestamp' feature"))
+}
+
+#[cfg(not(feature = "timestamp"))]
+pub fn format_timestamp(_secs: i64, _nanos: u32) -> Result<String> {
+    Err(Error::Static("smith-core was built without the 'timestamp' feature"))
+}
+
+/// Parses the fixed-length subset of ISO-8601 durations: `PnDTnHnMn.nS`.
+/// Years, months and weeks don't have a fixed length and are rejected
+pub fn parse_duration(s: &str) -> Result<(u64, u32)> {
+    let err = || invalid("duration", s);
+    let rest = s.strip_prefix('P').ok_or_else(err)?;
+    if rest.is_empty() || rest.ends_with('T') {
+        return Err(err());
+    }
+
+    let mut secs = 0u64;
+    let mut nanos = 0u32;
+    let mut in_time = false;
+    let mut num_start = 0;
+    let mut last_unit = 0;
+    for (i, c) in rest.char_indices() {
+        match c {
+            'T' if !in_time && i == num_start => {
+                in_time = true;
+                num_start = i + 1;
+            }
+            'D' | 'H' | 'M' | 'S' => {
+                let (order, unit) = match (c, in_time) {
+                    ('D', false) => (1, 86_400),
+                    ('H', true) => (2, 3_600),
+                    ('M', true) => (3, 60),
+                    ('S', true) => (4, 1),
+                    _ => return Err(err()),
+                };
+                if order <= last_unit {
+                    return Err(err());
+                }
+                last_unit = order;
+
+                let num = &rest[num_start..i];
+                let (whole, frac) = num.split_once('.').unwrap_or((num, ""));
+                if whole.is_empty() || !whole.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(err());
+                }
+                let whole: u64 = whole.parse().map_err(|_| err())?;
+                secs = whole
+                    .checked_mul(unit)
+                    .and_then(|v| secs.checked_add(v))
+                    .ok_or_else(err)?;
+                if num.contains('.') {
+                    if c != 'S' || frac.is_empty() || frac.len() > 9 {
+                        return Err(err());
+                    }
+                    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+                        return Err(err());
+                    }
+                    nanos = format!("{frac:0<9}").parse().map_err(|_| err())?;
+                }
+                num_start = i + 1;
+            }
+            '0'..='9' | '.' => {}
+            _ => return Err(err()),
+        }
+    }
+    if num_start != rest.len() {
+        return Err(err());
+    }
+    Ok((secs, nanos))
+}
+
+pub fn format_duration(secs: u64, nanos: u32) -> Result<String> {
+    if nanos >= NANOS_PER_SEC {
+        return Err(invalid("duration", &format!("{secs}s {nanos}ns")));
+    }
+    if nanos == 0 {
+        return Ok(format!("PT{secs}S"));
+    }
+    let frac = format!("{nanos:09}");
+    Ok(format!("PT{secs}.{}S", frac.trim_end_matches('0')))
+}
+
+/// Accepts the hyphenated (`8-4-4-4-12`) and the simple (32 hex digits) form
+pub fn parse_uuid(s: &str) -> Result<[u8; 16]> {
+    let err = || invalid("uuid", s);
+    let hex = match s.len() {
+        32 => s.to_owned(),
+        36 => {
+            let b = s.as_bytes();
+            if [8, 13, 18, 23].iter().any(|i| b[*i] != b'-') {
+                return Err(err());
+            }
+            s.replace('-', "")
+        }
+        _ => return Err(err()),
+    };
+    if hex.len() != 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(err());
+    }
+
+    let mut out = [0u8; 16];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| err())?;
+    }
+    Ok(out)
+}
+
+pub fn format_uuid(bytes: &[u8; 16]) -> String {
+    let mut out = String::with_capacity(36);
+    for (i, b) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+        _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+/// Parses plain decimal notation (`-12.50`). The scale is the amount of fractional digits,
+/// trailing zeros are kept
+pub fn parse_decimal(s: &str) -> Result<(i64, u8)> {
+    let err = || invalid("decimal", s);
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (whole, frac) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && frac.is_empty() {
+        return Err(err());
+    }
+    let scale = u8::try_from(frac.len()).map_err(|_| err())?;
+
+    //Accumulated negative, i64::MIN has no positive counterpart
+    let mut mantissa = 0i64;
+    for b in whole.bytes().chain(frac.bytes()) {
+        if !b.is_ascii_digit() {
+            return Err(err());
+        }
+        mantissa = mantissa
+            .checked_mul(10)
+            .and_then(|m| m.checked_sub((b - b'0') as i64))
+            .ok_or_else(err)?;
+    }
+    if !neg {
+        mantissa = mantissa.checked_neg().ok_or_else(err)?;
+    }
+    Ok((mantissa, scale))
+}
+
+pub fn format_decimal(mantissa: i64, scale: u8) -> String {
+    let sign = if mantissa < 0 { "-" } else { "" };
+    let digits = mantissa.unsigned_abs().to_string();
+    if scale == 0 {
+        return format!("{sign}{digits}");
+    }
+    let scale = scale as usize;
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (whole, frac) = digits.split_at(digits.len() - scale);
+    format!("{sign}{whole}.{frac}")
+}
@@ -0,0 +1,105 @@
+//! Reused buffers reach an allocation free steady state.
+//! Own test binary, since the counting allocator is installed globally
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use serde::Serialize;
+use smith_core::Smith;
+
+struct CountingAlloc;
+
+thread_local! {
+    //Counted per thread - the test harness allocates concurrently
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+const SCHEMA: &str = r#"
+enum Kind{
+    Ping
+    Data(u32)
+}
+
+struct Msg{
+    id: udInt
+    name: string
+    values: Array<u16>
+    extra: Option<i32>
+    kind: Kind
+}
+"#;
+
+#[derive(Serialize)]
+enum Kind {
+    Ping,
+    Data(u32),
+}
+
+#[derive(Serialize)]
+struct Msg {
+    id: u64,
+    name: String,
+    values: Vec<u16>,
+    extra: Option<i32>,
+    kind: Kind,
+}
+
+#[test]
+fn test_steady_state_does_not_allocate() {
+    let smith = Smith::new(SCHEMA);
+    let typ = smith.get_type("Msg").unwrap();
+    let msgs: Vec<_> = (0..100u64)
+        .map(|i| Msg {
+            id: i * 1000,
+            name: format!("message {i}"),
+            values: (0..i as u16 % 10).collect(),
+            extra: (i % 2 == 0).then_some(-(i as i32)),
+            kind: if i % 3 == 0 { Kind::Ping } else { Kind::Data(i as u32) },
+        })
+        .collect();
+
+    //Warm up - the buffers grow to the largest message
+    let mut buff = Vec::new();
+    let mut serializer = smith.serializer(&typ);
+    for msg in &msgs {
+        buff.clear();
+        smith.encode_into(msg, &typ, &mut buff).unwrap();
+        serializer.reset(&typ);
+        msg.serialize(&mut serializer).unwrap();
+    }
+
+    let before = allocations();
+    let mut total = 0;
+    for msg in &msgs {
+        buff.clear();
+        smith.encode_into(msg, &typ, &mut buff).unwrap();
+        serializer.reset(&typ);
+        msg.serialize(&mut serializer).unwrap();
+        assert_eq!(serializer.as_bytes(), &buff[..]);
+        total += buff.len();
+    }
+    assert_eq!(allocations() - before, 0);
+    assert!(total > 0);
+}
@@ -0,0 +1,235 @@
+//! Mutation fuzzing of the decoders, `extract`, `events`, `validate` and `dump` - malformed
+//! input fails with an error.
+//! Runs a short round with `cargo test`, longer locally:
+//! `SMITH_FUZZ_ITERS=1000000 cargo test --release -p smith-core --test fuzz`.
+//! A failing input is printed with the seed, `SMITH_FUZZ_SEED` repeats the run
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+
+use serde::{Deserialize, Serialize};
+use smith_core::{
+    DecodeLimits, DecodeOptions, ParallelOptions, Smith, SmithType, StringEncoding, WireFormat,
+};
+
+const SCHEMA: &str = r#"
+flags Perms { Read Write Exec }
+
+struct Point{
+    x: dInt
+    y: i16
+}
+
+enum Shape{
+    Empty
+    Circle(f32)
+    Poly(Array<Point>)
+}
+
+struct Node{
+    name: string
+    children: Array<Node>
+    next: Option<Node>
+}
+
+struct Msg{
+    id: udInt
+    tags: Map<string,u8>
+    shape: Shape
+    perms: Perms
+    data: bytes
+    fixed: [u16;3]
+    tree: Node
+    at: timestamp
+    took: duration
+    uid: uuid
+    price: decimal
+}
+
+struct Points{
+    points: Array<Point>
+}
+"#;
+
+const SEEDS: &[&str] = &[
+    r#"{"id":0,"tags":{},"shape":{"tag":"Empty"},"perms":[],"data":"","fixed":[0,0,0],
+        "tree":{"name":"","children":[],"next":null},"at":"1970-01-01T00:00:00Z","took":"PT0S",
+        "uid":"00000000-0000-0000-0000-000000000000","price":"0"}"#,
+    r#"{"id":300000,"tags":{"a":1,"bc":2},"shape":{"tag":"Circle","val":1.5},
+        "perms":["Read","Exec"],"data":"AAEC","fixed":[1,2,65535],
+        "tree":{"name":"root","children":[{"name":"a","children":[],"next":null}],
+            "next":{"name":"b","children":[],"next":null}},
+        "at":"2023-04-01T12:30:00.250Z","took":"PT90.5S",
+        "uid":"67e55044-10b1-426f-9247-bb680e5fe0c8","price":"-12.50"}"#,
+    r#"{"id":1,"tags":{"x":255},"shape":{"tag":"Poly","val":[{"x":-5,"y":3},{"x":70000,"y":-1}]},
+        "perms":["Write"],"data":"/w==","fixed":[7,8,9],
+        "tree":{"name":"n","children":[],"next":null},"at":"1900-01-01T00:00:00Z",
+        "took":"PT1M","uid":"67e55044-10b1-426f-9247-bb680e5fe0c8","price":"3.14159"}"#,
+];
+
+//Only decoded, the fields are never read
+#[allow(dead_code)]
+#[derive(Serialize, Deserialize, Debug)]
+struct Point {
+    x: i64,
+    y: i16,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+enum Shape {
+    Empty,
+    Circle(f32),
+    Poly(#[serde(deserialize_with = "smith_core::par::deserialize")] Vec<Point>),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+struct Node {
+    name: String,
+    children: Vec<Node>,
+    next: Option<Box<Node>>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+struct Msg {
+    id: u64,
+    tags: HashMap<String, u8>,
+    shape: Shape,
+    perms: u8,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    fixed: [u16; 3],
+    tree: Node,
+    at: String,
+    took: std::time::Duration,
+    uid: String,
+    price: String,
+}
+
+/// Encoded with a chunk table by the parallel smith
+#[derive(Serialize, Deserialize, Debug)]
+struct Points {
+    #[serde(with = "smith_core::par")]
+    points: Vec<Point>,
+}
+
+/// xorshift64* - no dependency, reproducible from the seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        const INTERESTING: [u8; 6] = [0, 1, 0x7f, 0x80, 0xfe, 0xff];
+        match self.below(3) {
+            0 => INTERESTING[self.below(INTERESTING.len())],
+            _ => self.next() as u8,
+        }
+    }
+}
+
+fn mutate(rng: &mut Rng, data: &mut Vec<u8>) {
+    let pos = rng.below(data.len() + 1);
+    match rng.below(6) {
+        0 if pos < data.len() => data[pos] ^= 1 << rng.below(8),
+        1 if pos < data.len() => data[pos] = rng.byte(),
+        2 => data.truncate(pos),
+        3 => {
+            let bytes: Vec<u8> = (0..1 + rng.below(8)).map(|_| rng.byte()).collect();
+            data.splice(pos..pos, bytes);
+        }
+        //Largest udInt / dInt - huge lengths and variant indices
+        4 => {
+            data.splice(pos..pos, [0xff; 8]);
+        }
+        _ if pos < data.len() => {
+            data.remove(pos);
+        }
+        _ => data.push(rng.byte()),
+    }
+}
+
+fn decode_all(smith: &Smith, typ: &SmithType<usize>, data: &[u8]) {
+    let _ = smith.binary2rust::<Msg>(data, typ);
+    let _ = smith.binary2rust::<Points>(data, typ);
+    let _ = smith.binary2rust::<serde_json::Value>(data, typ);
+    let _ = smith.binary2json(data, typ);
+    for path in ["price", "tree.children.0.next.name", "shape.Poly.1.y", "points.2.x"] {
+        let _ = smith.extract(data, typ, path);
+    }
+    if let Ok(events) = smith.events(data, typ) {
+        events.for_each(drop);
+    }
+    let _ = smith.dump(data, typ).to_string();
+    //Validated messages decode
+    if smith.validate(data, typ).is_ok() {
+        smith.decode_value(data, typ).expect("validated message failed to decode");
+    }
+}
+
+fn env(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+#[test]
+fn fuzz_decoders() {
+    let iterations = env("SMITH_FUZZ_ITERS").unwrap_or(3000);
+    let seed = env("SMITH_FUZZ_SEED").unwrap_or(0x5eed_5eed_5eed_5eed);
+
+    let limits = DecodeLimits::default()
+        .with_max_depth(16)
+        .with_max_array_elements(1 << 16)
+        .with_max_string_len(1 << 12);
+    let smiths = [
+        Smith::new(SCHEMA),
+        Smith::new(SCHEMA)
+            .with_format(WireFormat::default().with_strings(StringEncoding::LengthPrefixed))
+            .with_decode_options(DecodeOptions::default().with_limits(limits)),
+        Smith::new(SCHEMA)
+            .with_parallel(ParallelOptions::default().with_min_len(1).with_chunk_table(true))
+            .with_decode_options(DecodeOptions::default().with_parallel(true)),
+    ];
+
+    let mut rng = Rng(seed | 1);
+    for (smith, name) in smiths.iter().flat_map(|s| [(s, "Msg"), (s, "Points")]) {
+        let typ = smith.get_type(name).unwrap();
+        let corpus: Vec<Vec<u8>> = if name == "Msg" {
+            SEEDS
+                .iter()
+                .map(|json| smith.json2binary(json, &typ).unwrap().into_vec())
+                .collect()
+        } else {
+            [0, 3, 40]
+                .map(|len| Points {
+                    points: (0..len).map(|i| Point { x: i * 1000 - 7, y: i as i16 }).collect(),
+                })
+                .iter()
+                .map(|points| smith.rust2binary(points, &typ).unwrap().into_vec())
+                .collect()
+        };
+
+        for i in 0..iterations {
+            let mut data = match rng.below(10) {
+                0 => (0..rng.below(64)).map(|_| rng.byte()).collect(),
+                _ => corpus[rng.below(corpus.len())].clone(),
+            };
+            for _ in 0..1 + rng.below(4) {
+                mutate(&mut rng, &mut data);
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| decode_all(smith, &typ, &data)));
+            if result.is_err() {
+                panic!("decoder panicked - seed {seed}, iteration {i}, input {data:02x?}");
+            }
+        }
+    }
+}
@@ -1,6 +1,3 @@
-// #![feature(specialization)]
-// mod smith_asyncvec;
-// use smith_asyncvec::AsyncVec;
 use std::{fs, time::{Instant, Duration}, ops::{Add, Deref, DerefMut}, todo, println};
 
 use Types::Root;
@@ -9,7 +6,7 @@ use minify::json::minify;
 use protobuf::{Message, well_known_types::struct_::value};
 use protos::schema::SomeMessage;
 use serde_json::Value;
-use smith_core::Smith;
+use smith_core::{ParallelOptions, Smith};
 mod protos;
 
 
@@ -74,6 +71,10 @@ fn main() {
     bench("Smith serialize",runs, ||{
         _=smith.rust2binary(&root, &typ);
     });
+    let smith_par = smith.clone().with_parallel(ParallelOptions::default());
+    bench("Smith serialize (parallel)",runs, ||{
+        _=smith_par.rust2binary(&root, &typ);
+    });
 
     bench("SerdeJson deserialize (dynamic - min)",runs, ||{
         _=serde_json::to_value(&jsonmin);
@@ -211,7 +212,7 @@ pub struct SimplePerson<'a> {
 }
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Root<'a> {
-    #[serde(borrow)]
+    #[serde(borrow, serialize_with = "smith_core::par::serialize")]
     pub person: Vec<Person<'a>>,
 }
 
@@ -60,6 +60,9 @@ pub fn show_imps_map(schema: &str) -> String{
                     svar.pop();
                     _=s2.write_str(&format!("[{svar}]"));
                 },
+                smith_core::parser::ASTRootType::Flags(s) => {
+                    _=s2.write_str(&format!("{:?}",s.flags));
+                },
             }
 
             _=s.write_str(&format!("      [{}] => {}\n", format_vec(keytype),s2));